@@ -72,7 +72,7 @@ class RawBitBoost:
     _rust_dealloc.restype = c_int
 
     _rust_refresh_data = _lib.bb_refresh_data
-    _rust_refresh_data.argtypes = [c_void_p, c_int]
+    _rust_refresh_data.argtypes = [c_void_p, c_size_t]
     _rust_refresh_data.restype = c_int
 
     _rust_set_fdata = _lib.bb_set_feature_data
@@ -83,6 +83,25 @@ class RawBitBoost:
     _rust_set_config_field.argtypes = [c_void_p, c_char_p, c_char_p]
     _rust_set_config_field.restype = c_int
 
+    _rust_set_init_scores = _lib.bb_set_init_scores
+    _rust_set_init_scores.argtypes = [c_void_p, numt_p]
+    _rust_set_init_scores.restype = c_int
+
+    _bias_fn_t = CFUNCTYPE(c_int, c_void_p, c_size_t, numt_p, numt_p)
+    _gradient_fn_t = CFUNCTYPE(c_int, c_void_p, c_size_t, numt_p, numt_p, numt_p, numt_p)
+    _leaf_value_fn_t = CFUNCTYPE(c_int, c_void_p, c_size_t, POINTER(c_size_t), c_size_t,
+                                 numt_p, numt_p, numt_p, numt_p)
+    _metric_fn_t = CFUNCTYPE(c_int, c_void_p, c_size_t, numt_p, numt_p, numt_p)
+
+    _rust_set_custom_objective = _lib.bb_set_custom_objective
+    _rust_set_custom_objective.argtypes = [c_void_p, c_void_p, _bias_fn_t, _gradient_fn_t,
+                                           _leaf_value_fn_t]
+    _rust_set_custom_objective.restype = c_int
+
+    _rust_add_custom_metric = _lib.bb_add_custom_metric
+    _rust_add_custom_metric.argtypes = [c_void_p, c_char_p, c_int, c_void_p, _metric_fn_t]
+    _rust_add_custom_metric.restype = c_int
+
     _rust_train = _lib.bb_train
     _rust_train.argtypes = [c_void_p]
     _rust_train.restype = c_int
@@ -97,6 +116,8 @@ class RawBitBoost:
         self._nfeatures = nfeatures
         self._nexamples = -1 # set by set_data
         self._ctx_ptr = self._rust_alloc(self._nfeatures)
+        self._callbacks = [] # keep ctypes callbacks alive while the context is
+        self._callback_error = None # first exception raised by a callback, re-raised by train
 
     def __del__(self):
         if self._ctx_ptr:
@@ -139,9 +160,23 @@ class RawBitBoost:
         self._check()
         self.set_feature_data(self._nfeatures, data, False)
 
+    def set_init_scores(self, data):
+        """ Initial score (base margin) of each example; set after `set_data`. """
+        self._check()
+        assert isinstance(data, np.ndarray)
+        assert data.dtype == self.numt
+        assert data.shape[0] == self._nexamples
+        data = data.copy() # make copy to ensure congiguous, not optimal
+        data_ptr = data.ctypes.data_as(self.numt_p)
+        self._rust_set_init_scores(self._ctx_ptr, data_ptr)
+
     def set_config_field(self, name, value):
         self._check()
-        if self.config_params[name].type_str.startswith("Vec"):
+        type_str = self.config_params[name].type_str
+        if type_str.startswith("Vec<Vec"):
+            if not isinstance(value, str):
+                value = ";".join(",".join(map(str, group)) for group in value)
+        elif type_str.startswith("Vec"):
             if not isinstance(value, str):
                 value = ",".join(map(str, value))
         n = c_char_p(bytes(str(name), "utf8"))
@@ -154,9 +189,85 @@ class RawBitBoost:
         for name, value in values.items():
             self.set_config_field(name, value)
 
+    def set_custom_objective(self, gradient_fn, leaf_value_fn=None, bias_fn=None):
+        """ Use a custom objective instead of the `objective` config field.
+
+        gradient_fn(targets, predictions) -> (gradients, (lo, hi))
+        leaf_value_fn(examples, targets, predictions, gradients) -> value
+        bias_fn(targets) -> bias
+
+        The callbacks are called one at a time, but possibly from a training thread of the
+        library instead of the calling thread. An exception raised by a callback stops training
+        and is re-raised by `train`.
+        """
+        self._check()
+        as_array = np.ctypeslib.as_array
+
+        def gradient_cb(_, n, targets, predictions, gradients_out, bounds_out):
+            try:
+                grads, bounds = gradient_fn(as_array(targets, (n,)), as_array(predictions, (n,)))
+                as_array(gradients_out, (n,))[:] = grads
+                bounds_out[0], bounds_out[1] = bounds
+                return 0
+            except BaseException as e:
+                return self._set_callback_error(e)
+
+        def leaf_value_cb(_, nleaf, examples, n, targets, predictions, gradients, value_out):
+            try:
+                value_out[0] = leaf_value_fn(as_array(examples, (nleaf,)),
+                        as_array(targets, (n,)), as_array(predictions, (n,)),
+                        as_array(gradients, (n,)))
+                return 0
+            except BaseException as e:
+                return self._set_callback_error(e)
+
+        def bias_cb(_, n, targets, bias_out):
+            try:
+                bias_out[0] = bias_fn(as_array(targets, (n,)))
+                return 0
+            except BaseException as e:
+                return self._set_callback_error(e)
+
+        g = self._gradient_fn_t(gradient_cb)
+        l = self._leaf_value_fn_t(leaf_value_cb if leaf_value_fn else 0)
+        b = self._bias_fn_t(bias_cb if bias_fn else 0)
+        self._callbacks += [g, l, b]
+        self._rust_set_custom_objective(self._ctx_ptr, None, b, g, l)
+
+    def add_custom_metric(self, name, metric_fn, higher_is_better=False):
+        """ Evaluate a custom metric during training: metric_fn(targets, predictions) -> value
+
+        An exception raised by metric_fn stops training and is re-raised by `train`.
+        """
+        self._check()
+        as_array = np.ctypeslib.as_array
+
+        def metric_cb(_, n, targets, predictions, value_out):
+            try:
+                value_out[0] = metric_fn(as_array(targets, (n,)), as_array(predictions, (n,)))
+                return 0
+            except BaseException as e:
+                return self._set_callback_error(e)
+
+        m = self._metric_fn_t(metric_cb)
+        self._callbacks.append(m)
+        n = c_char_p(bytes(str(name), "utf8"))
+        self._rust_add_custom_metric(self._ctx_ptr, n, 1 if higher_is_better else 0, None, m)
+
+    def _set_callback_error(self, e):
+        """ Keep the first exception raised by a callback for `train`; returns the failure status
+        the callbacks report to the library. """
+        if self._callback_error is None:
+            self._callback_error = e
+        return 1
+
     def train(self):
         self._check()
-        self._rust_train(self._ctx_ptr)
+        self._callback_error = None
+        status = self._rust_train(self._ctx_ptr)
+        error, self._callback_error = self._callback_error, None
+        if status != 0 and error is not None:
+            raise error
 
     def predict(self):
         self._check()
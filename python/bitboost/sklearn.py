@@ -20,7 +20,7 @@ class BitBoost(BaseEstimator):
 
     __init__ = gen_init_fun(RawBitBoost.config_params, __file__)
 
-    def fit(self, X, y):
+    def fit(self, X, y, init_score=None):
         """ Fit a BitBoost model to training examples (X, y).
 
         Parameters
@@ -30,6 +30,8 @@ class BitBoost(BaseEstimator):
         y : {pandas.Series}, shape (n_samples,)
             The target values (class labels in classification, real numbers in
             regression).
+        init_score : {array-like}, shape (n_samples,), optional
+            Initial score (base margin) of each example.
 
         Returns
         -------
@@ -41,23 +43,28 @@ class BitBoost(BaseEstimator):
 
         nexamples, nfeatures = X.shape
 
-        self._bitboost = RawBitBoost(nfeatures, nexamples)
+        if not (self.warm_start and hasattr(self, "_bitboost")):
+            self._bitboost = RawBitBoost(nfeatures, nexamples)
         self._bitboost.set_config(self.get_params())
         self._bitboost.set_data(X, self.categorical_features)
         self._bitboost.set_target(y)
+        if init_score is not None:
+            self._bitboost.set_init_scores(np.asarray(init_score, dtype=self.numt))
 
         self._bitboost.train()
 
         self._is_fitted = True
         return self
 
-    def predict(self, X):
+    def predict(self, X, init_score=None):
         """ Predict values for given input data.
 
         Parameters
         ----------
         X : {pandas.DataFrame}, shape (n_samples, n_features)
             The training input samples.
+        init_score : {array-like}, shape (n_samples,), optional
+            Initial score (base margin) of each example, added to the predictions.
 
         Returns
         -------
@@ -68,6 +75,8 @@ class BitBoost(BaseEstimator):
         check_is_fitted(self, "_is_fitted")
 
         self._bitboost.set_data(X)
+        if init_score is not None:
+            self._bitboost.set_init_scores(np.asarray(init_score, dtype=self.numt))
         return self._bitboost.predict()
 
     def _check_sklearn_estimator(self):
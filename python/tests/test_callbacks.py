@@ -0,0 +1,82 @@
+# Copyright 2019 DTAI Research Group - KU Leuven.
+# License: Apache License 2.0
+# Author: Laurens Devos
+
+"""
+Custom objective and metric callbacks of RawBitBoost. Needs a built BitBoost library (`cargo
+build`); run with `python -m unittest discover python/tests`.
+"""
+
+import sys
+import os
+import unittest
+
+# use local python package rather than the system install
+sys.path.insert(0, os.path.join(os.path.dirname(__file__), ".."))
+
+from bitboost.bitboost import RawBitBoost
+import numpy as np
+
+
+class CallbackError(Exception):
+    pass
+
+def l2_gradient(targets, predictions):
+    residuals = targets - predictions
+    bound = min(abs(residuals.min()), abs(residuals.max()))
+    return -residuals, (-bound, bound)
+
+def failing(*args):
+    raise CallbackError("callback failed")
+
+
+class TestCallbacks(unittest.TestCase):
+
+    def booster(self):
+        nexamples = 500
+        x0 = np.arange(nexamples) % 23
+        x1 = np.arange(nexamples) % 7
+        data = np.column_stack((x0, x1)).astype(RawBitBoost.numt)
+        bb = RawBitBoost(2, nexamples)
+        bb.set_config({"niterations": 5, "verbosity": 0})
+        bb.set_data(data)
+        bb.set_target((x0 * x1).astype(RawBitBoost.numt))
+        return bb
+
+    def test_gradient_error(self):
+        bb = self.booster()
+        bb.set_custom_objective(failing)
+        with self.assertRaises(CallbackError):
+            bb.train()
+
+    def test_leaf_value_error(self):
+        bb = self.booster()
+        bb.set_custom_objective(l2_gradient, leaf_value_fn=failing)
+        with self.assertRaises(CallbackError):
+            bb.train()
+
+    def test_bias_error(self):
+        bb = self.booster()
+        bb.set_custom_objective(l2_gradient, bias_fn=failing)
+        with self.assertRaises(CallbackError):
+            bb.train()
+
+    def test_metric_error(self):
+        bb = self.booster()
+        bb.set_config_field("objective", "l2")
+        bb.add_custom_metric("failing", failing)
+        with self.assertRaises(CallbackError):
+            bb.train()
+
+    def test_train_after_error(self):
+        bb = self.booster()
+        bb.set_custom_objective(failing)
+        with self.assertRaises(CallbackError):
+            bb.train()
+        bb.set_custom_objective(l2_gradient)
+        bb.train()
+        self.assertTrue(np.all(np.isfinite(bb.predict())))
+
+
+if __name__ == "__main__":
+    unittest.main()
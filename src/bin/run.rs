@@ -7,6 +7,7 @@
 use std::env;
 //use std::time::Instant;
 use cpu_time::ProcessTime;
+use rayon::ThreadPoolBuilder;
 
 
 //use crossbeam_utils::thread;
@@ -20,10 +21,9 @@ use bitboost::tree::{AdditiveTree, Tree};
 use bitboost::tree::{TreeLearner, TreeLearnerContext};
 use bitboost::metric::{Metric, metrics_from_names};
 use bitboost::boost::Booster;
+use bitboost::checkpoint::Checkpoint;
 
 pub fn main() -> Result<(), String> {
-    pretty_env_logger::init();
-
     let args: Vec<String> = env::args().collect();
     match args.get(1).map(|x| x.as_str()) {
         Some("tree")  => {
@@ -35,6 +35,7 @@ pub fn main() -> Result<(), String> {
             let (conf, d_train, d_test, mut obj, ms) = parse(&args[2..])?;
             let (model, _) = boost(&conf, &d_train, obj.as_mut())?;
             summary(&conf, |d| model.predict(d), &d_train, d_test.as_ref(), obj.as_ref(), &ms);
+            check_monotone(&conf, &model, &d_train);
         },
         Some("boost_and_predict_raw") => {
             let (conf, d_train, d_test, mut obj, _) = parse(&args[2..])?;
@@ -60,13 +61,25 @@ fn parse(args: &[String])
     -> Result<(Config, Data, Option<Data>, Box<dyn Objective>, Vec<Box<dyn Metric>>), String>
 {
     let config = Config::parse(args.iter().map(|x| x.as_str()))?;
+    init_logger(&config);
     let objective = objective_from_name(&config.objective)
         .ok_or(format!("unknown objective '{}'", config.objective))?;
-    let ms = metrics_from_names(&config.metrics).ok_or("unknown metric".to_string())?;
+    let ms = metrics_from_names(&config.metrics, &config)
+        .ok_or("unknown metric".to_string())?;
     let (train_data, test_data) = load_data(&config)?;
     Ok((config, train_data, test_data, objective, ms))
 }
 
+/// Log level from the `verbosity` config field, RUST_LOG overrides it if set.
+fn init_logger(config: &Config) {
+    let mut builder = pretty_env_logger::formatted_builder();
+    builder.filter_level(config.log_level_filter());
+    if let Ok(filters) = env::var("RUST_LOG") {
+        builder.parse_filters(&filters);
+    }
+    builder.init();
+}
+
 fn load_data(config: &Config) -> Result<(Data, Option<Data>), String> {
     //let (train_data_res, test_data_res) = thread::scope(|s| {
         //let t1 = s.spawn(move |_| {
@@ -98,17 +111,22 @@ fn load_data(config: &Config) -> Result<(Data, Option<Data>), String> {
 fn single_tree(config: &Config, d_train: &Data, objective: &mut dyn Objective)
     -> Result<Tree, String>
 {
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(config.nthreads)
+        .build()
+        .map_err(|err| format!("cannot create thread pool: {}", err))?;
+
     let target = d_train.get_target();
     objective.initialize(&config, &target);
-    objective.update(&target);
+    pool.install(|| objective.update(&target));
 
     let mut dataset = Dataset::new(&config, &d_train);
-    dataset.update(&config, objective.gradients(), objective.bounds());
+    pool.install(|| dataset.update(&config, objective.gradients(), objective.bounds()));
 
     let mut context = TreeLearnerContext::new(&config, &d_train);
-    let supercats = dataset.get_supercats().clone();
+    let supercats = dataset.get_supercats();
     let learner = TreeLearner::new(&mut context, &dataset, supercats, objective);
-    let mut tree = learner.train();
+    let mut tree = pool.install(|| learner.train());
     tree.set_bias(objective.bias());
 
     Ok(tree)
@@ -117,8 +135,14 @@ fn single_tree(config: &Config, d_train: &Data, objective: &mut dyn Objective)
 fn boost(config: &Config, d_train: &Data, objective: &mut dyn Objective)
     -> Result<(AdditiveTree, f32), String>
 {
-    let ms = metrics_from_names(&config.metrics).ok_or("unknown metric".to_string())?;
-    let booster = Booster::new(&config, &d_train, objective, &ms);
+    let ms = metrics_from_names(&config.metrics, &config)
+        .ok_or("unknown metric".to_string())?;
+    let booster = if config.resume_from.is_empty() {
+        Booster::new(&config, &d_train, objective, &ms)
+    } else {
+        let checkpoint = Checkpoint::load(&config.resume_from)?;
+        Booster::resume(&config, &d_train, objective, &ms, checkpoint)?
+    };
 
     let start = ProcessTime::now();
     let model = booster.train();
@@ -164,6 +188,16 @@ where F: Fn(&Data) -> Vec<NumT>
     }
 }
 
+fn check_monotone(config: &Config, model: &AdditiveTree, train: &Data) {
+    for (feat_id, &c) in config.monotone_constraints.iter().enumerate() {
+        if c == 0 { continue; }
+        match model.check_monotone(train, feat_id, c, 100) {
+            Ok(()) => println!("[   ] monotone F{:02} ({:+}): ok", feat_id, c),
+            Err(msg) => println!("[   ] monotone F{:02} ({:+}): {}", feat_id, c, msg),
+        }
+    }
+}
+
 #[allow(dead_code)]
 fn print_predictions(target: &[NumT], prediction: &[NumT], npreds: usize) {
     println!("{:4}  {:>15} {:>15} {:>15}", "", "target", "prediction", "error");
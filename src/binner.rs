@@ -4,6 +4,7 @@
  * Author: Laurens Devos
 */
 
+use std::fmt::Display;
 use std::ops::AddAssign;
 
 use crate::NumT;
@@ -37,8 +38,9 @@ impl <'a, BinT> Binner<'a, BinT> {
         (combiner)(bin, data);
     }
 
-    pub fn bin_with_rank<F>(&self, rank: u32, extractor: F) -> usize
-    where F: Fn(&BinT) -> u32,
+    pub fn bin_with_rank<R, F>(&self, rank: R, extractor: F) -> usize
+    where R: Copy + Default + PartialOrd + AddAssign + Display,
+          F: Fn(&BinT) -> R,
     {
         let ranks = [rank];
         let iter = (&ranks).iter().cloned();
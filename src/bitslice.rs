@@ -24,6 +24,12 @@ pub trait BitsliceLayout {
     /// The number of unique values that can be represented by this BitSlice.
     fn nunique_values() -> usize { 1 << Self::width() }
 
+    /// The u32 within a 256-bit superblock holding the `lane`'th bits of the `superblock_j`'th
+    /// 32 values of that superblock.
+    fn superblock_offset(superblock_j: usize, lane: usize) -> usize {
+        (lane * Self::superblock_width()) + superblock_j
+    }
+
     fn linproj(value: NumT, count: NumT, (lo, hi): (NumT, NumT)) -> NumT {
         let maxval = (Self::nunique_values() - 1) as NumT;
         (value / maxval) * (hi - lo) + count * lo
@@ -86,6 +92,10 @@ impl Bitslice {
     pub fn as_bitblocks(&self) -> &BitBlocks {
         &self.blocks
     }
+
+    pub fn as_bitblocks_mut(&mut self) -> &mut BitBlocks {
+        &mut self.blocks
+    }
 }
 
 impl <B, L> BitsliceWithLayout<B, L>
@@ -139,7 +149,7 @@ where B: Borrow<BitBlocks>,
 
     /// Compute the linear u32 index into the BitSlice vec.
     fn bitslice_blockpos_u32(superblock_i: usize, superblock_j: usize, lane: usize) -> usize {
-         (superblock_i * 8) + (lane * L::superblock_width()) + superblock_j
+         (superblock_i * 8) + L::superblock_offset(superblock_j, lane)
     }
 
     /// Same as `get_block`, but w/o bounds checking
@@ -208,6 +218,19 @@ where B: Borrow<BitBlocks>,
         self.set_value(index, v2);
         v2 // return the discretized value
     }
+
+    /// Like `set_scaled_value`, but rounds up with a probability equal to the fractional part, so
+    /// that the expected discretized value equals the clipped value. `u` is uniform in [0, 1).
+    pub fn set_scaled_value_stochastic(&mut self, index: usize, value: NumT,
+                                       (lo, hi): (NumT, NumT), u: NumT) -> u8
+    where B: BorrowMut<BitBlocks> {
+        let maxval = (L::nunique_values() - 1) as NumT;
+        let v0 = NumT::min(hi, NumT::max(lo, value));
+        let v1 = ((v0 - lo) / (hi - lo)) * maxval;
+        let v2 = NumT::min(maxval, (v1 + u).floor()) as u8;
+        self.set_value(index, v2);
+        v2 // return the discretized value
+    }
 }
 
 
@@ -259,4 +282,22 @@ mod test {
             assert_eq!(k, view.get_value(i));
         }
     }
+
+    #[test]
+    fn bitslice_stochastic_rounding() {
+        let n = 10_000;
+        let value = 0.3; // between 0 and 1, the two levels of width 1
+        let mut bitslice = Bitslice::new();
+        let mut view = bitslice.with_layout_mut::<BitsliceLayout1>();
+        view.resize(n);
+
+        let mut sum = 0;
+        for i in 0..n {
+            let u = i as NumT / n as NumT; // uniform in [0, 1)
+            sum += view.set_scaled_value_stochastic(i, value, BOUNDS, u) as usize;
+        }
+        assert_eq!(sum, 3000);
+        assert_eq!(view.set_scaled_value_stochastic(0, 1.0, BOUNDS, 0.99), 1);
+        assert_eq!(view.set_scaled_value_stochastic(0, 0.0, BOUNDS, 0.99), 0);
+    }
 }
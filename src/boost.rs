@@ -5,14 +5,24 @@
 */
 
 use std::time::Instant;
+use std::fs::File;
+use std::io::BufWriter;
 
+use log::{warn, info, debug};
+use rand::Rng;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::NumT;
 use crate::config::Config;
-use crate::data::{Data};
-use crate::dataset::Dataset;
-use crate::tree::{AdditiveTree};
+use crate::data::{Data, FeatType};
+use crate::dataset::{Dataset, CountingRng};
+use crate::tree::{AdditiveTree, Tree};
 use crate::tree::{TreeLearnerContext, TreeLearner};
-use crate::objective::{Objective};
+use crate::objective::{Objective, count_clipped};
 use crate::metric::Metric;
+use crate::checkpoint::Checkpoint;
+use crate::callback::{TrainCallback, IterationInfo, CallbackAction};
+use crate::callback::{PrintProgress, EarlyStopping, JsonLog};
 
 macro_rules! time {
     ($($block:tt)*) => {{
@@ -35,6 +45,16 @@ pub struct Booster<'a> {
     iter_count: usize, 
     metrics: &'a [Box<dyn Metric>],
     ensemble: AdditiveTree,
+    warm_start: bool,
+    resume_predictions: Option<Vec<NumT>>,
+    rng: CountingRng,
+    base_predictions: Vec<NumT>, // rf: predictions without trees
+    oob_sums: Vec<NumT>,         // rf: sum of out-of-bag tree predictions per example
+    oob_counts: Vec<usize>,      // rf: number of trees for which example is out-of-bag
+    discr_nbits: usize,          // minimum discretization bits, raised by the error threshold
+    callbacks: Vec<Box<dyn TrainCallback + 'a>>,
+    early_stopping: Option<EarlyStopping>,
+    pool: ThreadPool,            // runs the objective and dataset updates and the tree learner
 }
 
 impl <'a> Booster<'a> {
@@ -44,6 +64,36 @@ impl <'a> Booster<'a> {
     {
         let ensemble = AdditiveTree::new();
         let dataset = Dataset::new(config, data);
+        let mut callbacks: Vec<Box<dyn TrainCallback + 'a>> = vec![Box::new(PrintProgress::new())];
+        let early_stopping = if config.early_stopping_rounds == 0 { None }
+                             else { Some(EarlyStopping::new(config.early_stopping_rounds)) };
+        match config.boosting.as_str() {
+            "gbdt" => {},
+            "rf"   => assert!(config.example_fraction < 1.0 || config.example_replacement,
+                              "random forest requires example sampling"),
+            "dart" => assert!(config.dart_normalize == "tree" || config.dart_normalize == "forest",
+                              "unknown dart_normalize '{}'", config.dart_normalize),
+            _      => panic!("unknown boosting type '{}'", config.boosting),
+        }
+        assert!(config.checkpoint_frequency == 0 || !config.checkpoint_path.is_empty(),
+                "checkpoint_frequency set but no checkpoint_path");
+        let schedule = &config.discr_nbits_schedule;
+        assert!(schedule.windows(2).all(|w| w[0] <= w[1]), "discr_nbits_schedule not sorted");
+        assert!(config.discr_nbits << schedule.len() <= 8, "discr_nbits_schedule: over 8 bits");
+        for (feat_id, &c) in config.monotone_constraints.iter().enumerate() {
+            assert!(c == 0 || (feat_id < data.nfeatures()
+                               && data.feat_type(feat_id) == FeatType::Numerical),
+                    "monotone constraint on non-numerical feature {}", feat_id);
+        }
+        if !config.training_log.is_empty() {
+            let file = File::create(&config.training_log)
+                .expect("cannot create training log file");
+            callbacks.push(Box::new(JsonLog::new(BufWriter::new(file))));
+        }
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(config.nthreads)
+            .build()
+            .expect("cannot create thread pool");
         Booster {
             config,
             data,
@@ -53,76 +103,798 @@ impl <'a> Booster<'a> {
             iter_count: 0,
             metrics,
             ensemble,
+            warm_start: false,
+            resume_predictions: None,
+            rng: CountingRng::new(config.random_seed.wrapping_add(1)),
+            base_predictions: Vec::new(),
+            oob_sums: Vec::new(),
+            oob_counts: Vec::new(),
+            discr_nbits: config.discr_nbits,
+            callbacks,
+            early_stopping,
+            pool,
         }
     }
 
-    pub fn train(mut self) -> AdditiveTree {
-        assert!(self.iter_count == 0);
+    /// Register a callback that is invoked after each iteration.
+    pub fn add_callback(&mut self, callback: Box<dyn TrainCallback + 'a>) {
+        self.callbacks.push(callback);
+    }
 
+    /// Continue training an existing model: the new trees are added to `ensemble`, and boosting
+    /// starts from the predictions of `ensemble` on `data` instead of from the objective's bias.
+    pub fn warm_start(config: &'a Config, data: &'a Data,
+                      objective: &'a mut dyn Objective,
+                      metrics: &'a [Box<dyn Metric>],
+                      ensemble: AdditiveTree) -> Booster<'a>
+    {
+        let mut booster = Booster::new(config, data, objective, metrics);
+        booster.ensemble = ensemble;
+        booster.warm_start = true;
+        booster
+    }
+
+    /// Resume training from a checkpoint written by a booster with the same configuration and
+    /// data.
+    pub fn resume(config: &'a Config, data: &'a Data,
+                  objective: &'a mut dyn Objective,
+                  metrics: &'a [Box<dyn Metric>],
+                  checkpoint: Checkpoint) -> Result<Booster<'a>, String>
+    {
+        if checkpoint.predictions.len() != data.nexamples() {
+            return Err(String::from("checkpoint: number of examples does not match data"));
+        }
+        let mut booster = Booster::new(config, data, objective, metrics);
+        booster.dataset.restore(config, &checkpoint.dataset)?;
+        booster.iter_count = checkpoint.iter_count;
+        booster.rng = CountingRng::restore(booster.rng.seed(), checkpoint.rng_ndraws);
+        booster.ensemble = checkpoint.ensemble;
+        booster.resume_predictions = Some(checkpoint.predictions);
+        booster.oob_sums = checkpoint.oob_sums;
+        booster.oob_counts = checkpoint.oob_counts;
+        booster.discr_nbits = checkpoint.discr_nbits;
+        Ok(booster)
+    }
+
+    pub fn train(mut self) -> AdditiveTree {
         self.print_intro();
 
         self.start = Instant::now();
         let target = self.data.get_target();
         let mut ctx = TreeLearnerContext::new(self.config, self.data);
 
-        self.objective.initialize(self.config, target);
-        self.ensemble.set_bias(self.objective.bias());
+        if self.config.boosting == "rf" {
+            assert!(!self.warm_start, "warm_start is not supported for random forests");
+            match self.data.init_scores() {
+                Some(offsets) => self.objective.initialize_with_offsets(self.config, target,
+                                                                        offsets),
+                None => self.objective.initialize(self.config, target),
+            }
+            self.base_predictions = self.objective.predictions().to_vec();
+            let n = self.data.nexamples();
+            self.oob_sums.resize(n, 0.0);
+            self.oob_counts.resize(n, 0);
+        }
+
+        if let Some(predictions) = self.resume_predictions.take() {
+            info!("[   ] resuming from iteration {}", self.iter_count);
+            self.objective.initialize_from_predictions(self.config, target, &predictions);
+        } else if self.warm_start {
+            let predictions = self.ensemble.predict(self.data);
+            self.objective.initialize_from_predictions(self.config, target, &predictions);
+        } else if let Some(offsets) = self.data.init_scores() {
+            self.objective.initialize_with_offsets(self.config, target, offsets);
+            self.ensemble.set_bias(self.objective.bias());
+        } else {
+            self.objective.initialize(self.config, target);
+            self.ensemble.set_bias(self.objective.bias());
+        }
 
-        for _ in 0..self.config.niterations {
-            self.train_one_iter(&mut ctx);
+        while self.iter_count < self.config.niterations {
+            let action = self.train_one_iter(&mut ctx);
+            if self.config.checkpoint_frequency > 0
+                && self.iter_count % self.config.checkpoint_frequency == 0
+            {
+                self.write_checkpoint();
+            }
+            if action == CallbackAction::Stop { break; }
+        }
+
+        // gbdt: return the model of the best iteration; the later iterations of dart and random
+        // forests change the weights of the earlier trees, so they keep all trees
+        if let Some(es) = &self.early_stopping {
+            let best = es.best_iteration();
+            if self.config.boosting == "gbdt" && best > 0 {
+                let ntrees = self.ensemble.ntrees() - (self.iter_count - best);
+                info!("[   ] early stopping: keeping {} trees", ntrees);
+                self.ensemble.truncate(ntrees);
+            }
         }
 
         self.ensemble
     }
 
-    fn train_one_iter(&mut self, ctx: &mut TreeLearnerContext) {
+    fn write_checkpoint(&self) {
+        let checkpoint = Checkpoint {
+            iter_count: self.iter_count,
+            rng_ndraws: self.rng.ndraws(),
+            ensemble: self.ensemble.clone(),
+            predictions: self.objective.predictions().to_vec(),
+            oob_sums: self.oob_sums.clone(),
+            oob_counts: self.oob_counts.clone(),
+            discr_nbits: self.discr_nbits,
+            dataset: self.dataset.checkpoint(),
+        };
+        match checkpoint.save(&self.config.checkpoint_path) {
+            Ok(()) => info!("[   ] checkpoint written to {}", self.config.checkpoint_path),
+            Err(err) => warn!("[ ! ] {}", err),
+        }
+    }
+
+    fn train_one_iter(&mut self, ctx: &mut TreeLearnerContext) -> CallbackAction {
         let target = self.data.get_target();
         self.iter_count += 1;
-        let (_, ot) = time!(self.objective.update(target));
-        let (_, dt) = time!(self.dataset.update(self.config, self.objective.gradients(),
-                                                self.objective.bounds()));
+        let dropped = if self.config.boosting == "dart" { self.dart_drop_trees() }
+                      else                              { Vec::new() };
+        let forest = if self.config.boosting == "rf" { Some(self.rf_reset_predictions()) }
+                     else                            { None };
+        let (config, pool) = (self.config, &self.pool);
+        let (objective, dataset) = (&mut *self.objective, &mut self.dataset);
+        let (nclipped, ot) = time!(pool.install(|| {
+            objective.update(target);
+            count_clipped(objective.gradients(), objective.bounds())
+        }));
+        let (_, dt) = time!(pool.install(|| {
+            dataset.update(config, objective.gradients(), objective.bounds())
+        }));
         // learn a tree
-        let supercats = self.dataset.get_supercats().clone();
+        if config.feature_fraction_bylevel < 1.0 || config.feature_fraction_bynode < 1.0 {
+            ctx.set_seed(self.rng.gen::<u64>());
+        }
+        if config.stochastic_rounding { // derived from the iteration, survives checkpoints
+            ctx.set_rounding_seed(config.random_seed.wrapping_add(self.iter_count as u64));
+        }
+        let discr_nbits = self.current_discr_nbits();
+        ctx.set_discr_nbits(discr_nbits);
+        let supercats = self.dataset.get_supercats();
         let learner = TreeLearner::new(ctx, &self.dataset, supercats, self.objective);
-        let (tree, tt) = time!(learner.train());
+        let (mut tree, tt) = time!(self.pool.install(|| learner.train()));
+        let error_threshold = config.discr_nbits_error_threshold;
+        if error_threshold > 0.0 && ctx.quantization_error() > error_threshold && discr_nbits < 8 {
+            self.discr_nbits = 2 * discr_nbits;
+            info!("[{:3}] quantization error {:.3}, using {} discretization bits", self.iter_count,
+                  ctx.quantization_error(), self.discr_nbits);
+        }
+        if !dropped.is_empty() {
+            self.dart_normalize(&mut tree, dropped);
+        }
+        if let Some(forest) = forest {
+            self.rf_average(&mut tree, forest);
+        }
 
-        // print updates
         let el = self.start.elapsed();
         let seconds = el.as_secs() as f32 + el.subsec_micros() as f32 * 1e-6;
-        println!("[{:3}] timings: objective {:5.1}, dataset {:5.1}, tree {:5.1} ms, total {:.3} s",
-                 self.iter_count, ot * 1000.0, dt * 1000.0, tt * 1000.0, seconds);
-        //let grad_bounds = self.objective.bounds();
-        //println!("[   ] gradient bounds ({}, {})", grad_bounds.0, grad_bounds.1);
 
         let run_metrics = !self.metrics.is_empty()
             && self.config.metric_frequency > 0
             && self.iter_count % self.config.metric_frequency == 0;
-        if run_metrics {
-            //let pred = tree.predict(self.data);
-            //let mag = pred.iter()
-            //    .map(|&x| x * x)
-            //    .fold(0.0, |x, y| x+y)
-            //    .sqrt() / pred.len() as NumT;
-            //println!("I{:03} tree value magnitude: {:e}", self.iter_count, mag);
-
-            for m in self.metrics {
-                let eval = m.eval(&target, self.objective.predictions());
-                println!("[   ] eval {:<13} {:10.4e}", m.name(), eval);
+        let metric_values: Vec<NumT> = if run_metrics {
+            self.metrics.iter()
+                .map(|m| m.eval(target, self.objective.predictions()))
+                .collect()
+        } else { Vec::new() };
+        let oob_metric_values: Vec<NumT> = if run_metrics && !self.oob_counts.is_empty() {
+            self.oob_metric_values()
+        } else { Vec::new() };
+
+        // notify the callbacks (e.g. print updates)
+        let info = IterationInfo {
+            iteration: self.iter_count,
+            tree: &tree,
+            objective_time: ot,
+            dataset_time: dt,
+            tree_time: tt,
+            total_time: seconds,
+            nclipped,
+            metrics: self.metrics,
+            metric_values: &metric_values,
+            oob_metric_values: &oob_metric_values,
+        };
+        let mut action = CallbackAction::Continue;
+        for callback in self.callbacks.iter_mut() {
+            if callback.after_iteration(&info) == CallbackAction::Stop {
+                action = CallbackAction::Stop;
+            }
+        }
+        if let Some(es) = self.early_stopping.as_mut() {
+            if es.after_iteration(&info) == CallbackAction::Stop {
+                action = CallbackAction::Stop;
             }
         }
 
         // shrinkage is automatically applied by objective!
         self.ensemble.push_tree(tree);
+        action
+    }
+
+    /// The number of bits used to discretize the gradients in the current iteration: doubled
+    /// after each iteration in `discr_nbits_schedule`, and at least the width set by the
+    /// quantization error threshold.
+    fn current_discr_nbits(&self) -> usize {
+        let nswitches = self.config.discr_nbits_schedule.iter()
+            .filter(|&&iter| iter < self.iter_count)
+            .count();
+        usize::max(self.config.discr_nbits << nswitches, self.discr_nbits)
+    }
+
+    /// DART: select the trees that are dropped in this iteration, and remove their contribution
+    /// from the predictions of the objective. Returns the indexes and the predictions of the
+    /// dropped trees.
+    fn dart_drop_trees(&mut self) -> Vec<(usize, Vec<NumT>)> {
+        let mut dropped = Vec::new();
+        if self.ensemble.ntrees() == 0 || self.rng.gen::<NumT>() < self.config.dart_skip_rate {
+            return dropped;
+        }
+        for (t, tree) in self.ensemble.trees().iter().enumerate() {
+            if self.rng.gen::<NumT>() >= self.config.dart_drop_rate { continue; }
+            let predictions = tree.predict(self.data);
+            for (i, &p) in predictions.iter().enumerate() {
+                self.objective.update_out_of_bag_prediction(i, -p);
+            }
+            dropped.push((t, predictions));
+        }
+        debug!("[{:3}] dart: dropped {} of {} trees", self.iter_count, dropped.len(),
+               self.ensemble.ntrees());
+        dropped
+    }
+
+    /// DART: scale the new tree and the dropped trees so that their combined contribution does not
+    /// overshoot, and add the dropped trees back to the predictions of the objective.
+    fn dart_normalize(&mut self, tree: &mut Tree, dropped: Vec<(usize, Vec<NumT>)>) {
+        let k = dropped.len() as NumT;
+        let lr = self.config.learning_rate;
+        let (drop_factor, new_factor) = match self.config.dart_normalize.as_str() {
+            "forest" => (1.0 / (1.0 + lr), 1.0 / (1.0 + lr)),
+            _        => (k / (k + lr), 1.0 / (k + lr)),
+        };
+
+        // the objective's predictions already contain the unscaled new tree
+        let predictions = tree.predict(self.data);
+        for (i, &p) in predictions.iter().enumerate() {
+            self.objective.update_out_of_bag_prediction(i, (new_factor - 1.0) * p);
+        }
+        tree.set_shrinkage(new_factor);
+
+        for (t, predictions) in dropped {
+            for (i, &p) in predictions.iter().enumerate() {
+                self.objective.update_out_of_bag_prediction(i, drop_factor * p);
+            }
+            self.ensemble.tree_mut(t).set_shrinkage(drop_factor);
+        }
+    }
+
+    /// Random forest: each tree fits the target starting from the base predictions. Returns the
+    /// current predictions of the forest.
+    fn rf_reset_predictions(&mut self) -> Vec<NumT> {
+        let forest = self.objective.predictions().to_vec();
+        for (i, (&f, &b)) in forest.iter().zip(&self.base_predictions).enumerate() {
+            self.objective.update_out_of_bag_prediction(i, b - f);
+        }
+        forest
+    }
+
+    /// Random forest: average the new tree with the existing trees, update the predictions of the
+    /// objective to those of the forest, and accumulate the out-of-bag predictions.
+    fn rf_average(&mut self, tree: &mut Tree, forest: Vec<NumT>) {
+        let t = self.iter_count as NumT;
+        let lr = self.config.learning_rate;
+        let tree_predictions: Vec<NumT> = tree.predict(self.data).iter().map(|&p| p / lr).collect();
+
+        let current = self.objective.predictions().to_vec();
+        for i in 0..current.len() {
+            let f = forest[i] + (tree_predictions[i] - (forest[i] - self.base_predictions[i])) / t;
+            self.objective.update_out_of_bag_prediction(i, f - current[i]);
+        }
+
+        for i in self.dataset.inactive_examples_iter() {
+            self.oob_sums[i] += tree_predictions[i];
+            self.oob_counts[i] += 1;
+        }
+
+        // all trees get weight 1/t, the learning rate is not used
+        for k in 0..self.ensemble.ntrees() {
+            self.ensemble.tree_mut(k).set_shrinkage((t - 1.0) / t);
+        }
+        tree.set_shrinkage(1.0 / (t * lr));
+    }
+
+    /// Random forest: evaluate the metrics on the examples that are out-of-bag for at least one
+    /// tree, using the average prediction of the trees for which they are out-of-bag.
+    fn oob_metric_values(&self) -> Vec<NumT> {
+        let target = self.data.get_target();
+        let mut oob_targets = Vec::new();
+        let mut oob_predictions = Vec::new();
+        for i in 0..self.oob_counts.len() {
+            if self.oob_counts[i] == 0 { continue; }
+            oob_targets.push(target[i]);
+            oob_predictions.push(self.base_predictions[i]
+                                 + self.oob_sums[i] / self.oob_counts[i] as NumT);
+        }
+        if oob_targets.is_empty() { return Vec::new(); }
+        self.metrics.iter()
+            .map(|m| m.eval(&oob_targets, &oob_predictions))
+            .collect()
     }
 
     fn print_intro(&self) {
         debug_assert!({
-            println!("[ ! ] debug build");
+            warn!("[ ! ] debug build");
             true
         });
         safety_check!({
-            println!("[ ! ] safety checks enabled");
+            warn!("[ ! ] safety checks enabled");
             true
         });
     }
 }
+
+
+
+
+
+
+
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::objective::objective_from_name;
+
+    #[test]
+    fn dart() {
+        let mut config = Config::new();
+        config.csv_has_header = false;
+        config.boosting = String::from("dart");
+        config.dart_drop_rate = 0.5;
+        config.dart_skip_rate = 0.0;
+        config.niterations = 20;
+        config.learning_rate = 0.5;
+        let csv: String = (0..200)
+            .map(|i| { let x = (i % 17) as NumT; format!("{},{},{}\n", x, i % 3, x * x) })
+            .collect();
+        let data = Data::from_csv(&config, &csv).unwrap();
+
+        for &normalize in &["tree", "forest"] {
+            config.dart_normalize = String::from(normalize);
+            let mut objective = objective_from_name("l2").unwrap();
+            let model = Booster::new(&config, &data, objective.as_mut(), &[]).train();
+
+            // the objective's predictions follow the reweighting of the trees
+            let predictions = model.predict(&data);
+            for (&p, &q) in predictions.iter().zip(objective.predictions()) {
+                assert!((p - q).abs() < 1e-3 * (1.0 + p.abs()), "{} vs {}", p, q);
+            }
+        }
+    }
+
+    /// Train on data with many copies per bag, and check that the training predictions of the
+    /// objective equal the predictions of the model.
+    fn check_replacement(config: &mut Config, objective: &str) {
+        config.csv_has_header = false;
+        config.niterations = 10;
+        config.example_replacement = true;
+        let csv: String = (0..300)
+            .map(|i| { let (x, y) = (i % 17, i % 3); format!("{},{},{}\n", x, y, (x + y) % 2) })
+            .collect();
+        let data = Data::from_csv(config, &csv).unwrap();
+        let mut objective = objective_from_name(objective).unwrap();
+        let model = Booster::new(config, &data, objective.as_mut(), &[]).train();
+        for (&p, &q) in objective.predictions().iter().zip(&model.predict(&data)) {
+            assert!((p - q).abs() < 1e-4, "{} vs {}", p, q);
+        }
+    }
+
+    #[test]
+    fn gbdt_replacement() {
+        let mut config = Config::new();
+        check_replacement(&mut config, "l2");
+    }
+
+    #[test]
+    fn stratified_replacement() {
+        let mut config = Config::new();
+        config.example_sampling = String::from("stratified");
+        config.pos_example_fraction = 1.5;
+        config.neg_example_fraction = 0.8;
+        check_replacement(&mut config, "binary");
+
+        // uniform sampling can over-sample too
+        let mut config = Config::new();
+        config.example_fraction = 2.0;
+        check_replacement(&mut config, "binary");
+    }
+
+    #[test]
+    fn early_stopping() {
+        let mut config = Config::new();
+        config.csv_has_header = false;
+        config.niterations = 50;
+        config.learning_rate = 0.5;
+        let csv: String = (0..200)
+            .map(|i| { let x = i % 20; format!("{},{}\n", x, if x < 8 { 0 } else { 1 }) })
+            .collect();
+        let data = Data::from_csv(&config, &csv).unwrap();
+        let metrics = vec![crate::metric::metric_from_name("binary_error", &config).unwrap()];
+        let train = |config: &Config| {
+            let mut objective = objective_from_name("binary").unwrap();
+            Booster::new(config, &data, objective.as_mut(), &metrics).train()
+        };
+
+        // the error is zero after the first tree, and it does not improve after that
+        config.early_stopping_rounds = 3;
+        let model = train(&config);
+        assert_eq!(model.ntrees(), 1);
+
+        config.early_stopping_rounds = 0;
+        config.niterations = 1;
+        assert_eq!(model.predict(&data), train(&config).predict(&data));
+    }
+
+    #[test]
+    fn offsets() {
+        let mut config = Config::new();
+        config.csv_has_header = false;
+        config.niterations = 10;
+        let csv: String = (0..300)
+            .map(|i| { let (x, y) = (i % 13, i % 5); format!("{},{},{}\n", x, y, x + 2 * y) })
+            .collect();
+        let plain = Data::from_csv(&config, &csv).unwrap();
+        let mut data = Data::from_csv(&config, &csv).unwrap();
+        let offsets: Vec<NumT> = (0..300).map(|i| (i % 5) as NumT).collect();
+        data.set_init_scores(&offsets);
+
+        let mut objective = objective_from_name("l2").unwrap();
+        let model = Booster::new(&config, &data, objective.as_mut(), &[]).train();
+
+        // predict adds the offsets back, and agrees with the training predictions
+        let with = model.predict(&data);
+        let without = model.predict(&plain);
+        for i in 0..300 {
+            assert!((with[i] - objective.predictions()[i]).abs() < 1e-4);
+            assert!((with[i] - without[i] - offsets[i]).abs() < 1e-4);
+        }
+        // the l2 prior is relative to the offsets: mean(target - offset)
+        let target = data.get_target();
+        let prior = (0..300).map(|i| target[i] - offsets[i]).sum::<NumT>() / 300.0;
+        assert!((objective.bias() - prior).abs() < 1e-4, "bias {}", objective.bias());
+    }
+
+    #[test]
+    fn warm_start() {
+        let mut config = Config::new();
+        config.csv_has_header = false;
+        let csv: String = (0..500)
+            .map(|i| { let (x, y) = (i % 23, i % 7); format!("{},{},{}\n", x, y, x * y) })
+            .collect();
+        let data = Data::from_csv(&config, &csv).unwrap();
+        let mut train = |niterations, ensemble: Option<AdditiveTree>| {
+            config.niterations = niterations;
+            let mut objective = objective_from_name("l2").unwrap();
+            match ensemble {
+                Some(ensemble) => Booster::warm_start(&config, &data, objective.as_mut(), &[],
+                                                      ensemble).train(),
+                None => Booster::new(&config, &data, objective.as_mut(), &[]).train(),
+            }
+        };
+
+        // 3 + 2 iterations continue where the first 3 stopped, and equal 5 iterations
+        let model3 = train(3, None);
+        let model5 = train(5, None);
+        let continued = train(2, Some(model3));
+        assert_eq!(continued.ntrees(), 5);
+        for (&p, &q) in continued.predict(&data).iter().zip(&model5.predict(&data)) {
+            assert!((p - q).abs() < 1e-4 * (1.0 + q.abs()), "{} vs {}", p, q);
+        }
+    }
+
+    #[test]
+    fn leafwise() {
+        let mut config = Config::new();
+        config.csv_has_header = false;
+        config.niterations = 3;
+        config.max_tree_depth = 30;
+        config.max_leaves = 5;
+        let csv: String = (0..500)
+            .map(|i| { let (x, y) = (i % 23, i % 7); format!("{},{},{}\n", x, y, x * y) })
+            .collect();
+        let data = Data::from_csv(&config, &csv).unwrap();
+
+        for &policy in &["depthwise", "leafwise"] {
+            config.growth_policy = String::from(policy);
+            let mut objective = objective_from_name("l2").unwrap();
+            let model = Booster::new(&config, &data, objective.as_mut(), &[]).train();
+            assert!(model.trees().iter().all(|t| t.nleafs() == 5));
+        }
+    }
+
+    #[test]
+    fn exact_gradients() {
+        let mut config = Config::new();
+        config.csv_has_header = false;
+        config.niterations = 5;
+        config.max_tree_depth = 5;
+        config.compression_threshold = 0.0;
+        config.exact_gradients = true;
+        let csv: String = (0..3000)
+            .map(|i| { let (x, y) = (i % 37, i % 11); format!("{},{},{}\n", x, y, x * y) })
+            .collect();
+        let data = Data::from_csv(&config, &csv).unwrap();
+
+        // the discretization of the gradients is not used
+        let predictions: Vec<Vec<NumT>> = [1, 8].iter().map(|&discr_nbits| {
+            config.discr_nbits = discr_nbits;
+            let mut objective = objective_from_name("l2").unwrap();
+            let model = Booster::new(&config, &data, objective.as_mut(), &[]).train();
+            model.predict(&data)
+        }).collect();
+        assert_eq!(predictions[0], predictions[1]);
+    }
+
+    #[test]
+    fn discr_nbits_schedule() {
+        let mut config = Config::new();
+        config.csv_has_header = false;
+        config.niterations = 5;
+        let csv: String = (0..1000)
+            .map(|i| { let (x, y) = (i % 37, i % 11); format!("{},{},{}\n", x, y, x * y) })
+            .collect();
+        let data = Data::from_csv(&config, &csv).unwrap();
+        let mut train = |discr_nbits, schedule: Vec<usize>| {
+            config.discr_nbits = discr_nbits;
+            config.discr_nbits_schedule = schedule;
+            let mut objective = objective_from_name("l2").unwrap();
+            Booster::new(&config, &data, objective.as_mut(), &[]).train().predict(&data)
+        };
+
+        assert_eq!(train(2, vec![0, 0]), train(8, vec![]));
+        assert_eq!(train(2, vec![100]), train(2, vec![]));
+        assert_ne!(train(2, vec![2]), train(2, vec![]));
+    }
+
+    struct ClippedLog<'a>(&'a mut Vec<usize>);
+
+    impl <'a> TrainCallback for ClippedLog<'a> {
+        fn after_iteration(&mut self, info: &IterationInfo) -> CallbackAction {
+            self.0.push(info.nclipped);
+            CallbackAction::Continue
+        }
+    }
+
+    #[test]
+    fn gradient_bounds() {
+        let mut config = Config::new();
+        config.csv_has_header = false;
+        config.niterations = 5;
+        config.gradient_bounds_quantile = 0.05;
+        let csv: String = (0..2000)
+            .map(|i| { let (x, y) = (i % 37, i % 11); format!("{},{},{}\n", x, y, x * y) })
+            .collect();
+        let data = Data::from_csv(&config, &csv).unwrap();
+        let mut train = |strategy: &str| {
+            config.gradient_bounds = String::from(strategy);
+            let mut objective = objective_from_name("l2").unwrap();
+            let mut nclipped = Vec::new();
+            let mut booster = Booster::new(&config, &data, objective.as_mut(), &[]);
+            booster.add_callback(Box::new(ClippedLog(&mut nclipped)));
+            let predictions = booster.train().predict(&data);
+            (predictions, nclipped)
+        };
+
+        // the l2 objective's own bounds are symmetric
+        let (objective_predictions, objective_nclipped) = train("objective");
+        let (symmetric_predictions, symmetric_nclipped) = train("symmetric");
+        assert_eq!(objective_predictions, symmetric_predictions);
+        assert_eq!(objective_nclipped, symmetric_nclipped);
+        assert!(objective_nclipped.iter().all(|&n| n > 0));
+
+        let (_, asymmetric_nclipped) = train("asymmetric");
+        assert_eq!(asymmetric_nclipped, vec![0; 5]);
+
+        let (_, percentile_nclipped) = train("percentile");
+        assert!(percentile_nclipped.iter().all(|&n| 0 < n && n < 400));
+    }
+
+    #[test]
+    fn nthreads_deterministic() {
+        let mut config = Config::new();
+        config.csv_has_header = false;
+        config.niterations = 5;
+        config.max_tree_depth = 6;
+        config.example_fraction = 0.7;
+        config.compression_threshold = 0.1;
+        let csv: String = (0..20000)
+            .map(|i| { let (x, y) = (i % 101, i % 13); format!("{},{},{}\n", x, y, x * y) })
+            .collect();
+        let data = Data::from_csv(&config, &csv).unwrap();
+
+        let predictions: Vec<Vec<NumT>> = [1, 4].iter().map(|&nthreads| {
+            config.nthreads = nthreads;
+            let mut objective = objective_from_name("huber").unwrap();
+            let model = Booster::new(&config, &data, objective.as_mut(), &[]).train();
+            model.predict(&data)
+        }).collect();
+        assert_eq!(predictions[0], predictions[1]);
+    }
+
+    #[test]
+    fn monotone() {
+        let mut config = Config::new();
+        config.csv_has_header = false;
+        config.niterations = 20;
+        config.learning_rate = 0.5;
+        config.max_tree_depth = 4;
+        let csv: String = (0..600)
+            .map(|i| {
+                let (x, z) = ((i % 50) as NumT / 5.0, (i % 7) as NumT);
+                format!("{},{},{}\n", x, z, x + 2.0 * (2.0 * x).sin() - z)
+            })
+            .collect();
+        let data = Data::from_csv(&config, &csv).unwrap();
+
+        let mut objective = objective_from_name("l2").unwrap();
+        let model = Booster::new(&config, &data, objective.as_mut(), &[]).train();
+        assert!(model.check_monotone(&data, 0, 1, 50).is_err());
+
+        config.monotone_constraints = vec![1, -1];
+        let mut objective = objective_from_name("l2").unwrap();
+        let model = Booster::new(&config, &data, objective.as_mut(), &[]).train();
+        assert!(model.check_monotone(&data, 0, 1, 50).is_ok());
+        assert!(model.check_monotone(&data, 1, -1, 50).is_ok());
+
+        // clamped leaf values are reflected in the objective's predictions
+        let predictions = model.predict(&data);
+        for (&p, &q) in predictions.iter().zip(objective.predictions()) {
+            assert!((p - q).abs() < 1e-3 * (1.0 + p.abs()), "{} vs {}", p, q);
+        }
+    }
+
+    #[test]
+    fn interaction_constraints() {
+        let mut config = Config::new();
+        config.csv_has_header = false;
+        config.niterations = 10;
+        config.max_tree_depth = 4;
+        config.interaction_constraints = vec![vec![0], vec![1, 2]];
+        let csv: String = (0..500)
+            .map(|i| {
+                let (x, y, z) = (i % 11, i % 7, i % 5);
+                format!("{},{},{},{}\n", x, y, z, x * y + y * z)
+            })
+            .collect();
+        let data = Data::from_csv(&config, &csv).unwrap();
+
+        let mut objective = objective_from_name("l2").unwrap();
+        let model = Booster::new(&config, &data, objective.as_mut(), &[]).train();
+
+        for tree in model.trees() {
+            for leaf in (0..tree.nnodes()).filter(|&n| tree.is_leaf(n)) {
+                let mut features = Vec::new();
+                let mut node_id = leaf;
+                while node_id != 0 {
+                    node_id = tree.parent(node_id);
+                    features.push(tree.split_crit(node_id).feature_id);
+                }
+                assert!(config.interaction_constraints.iter()
+                        .any(|g| features.iter().all(|f| g.contains(f))), "{:?}", features);
+            }
+        }
+    }
+
+    #[test]
+    fn feature_fraction_bylevel() {
+        let mut config = Config::new();
+        config.csv_has_header = false;
+        config.niterations = 5;
+        config.max_tree_depth = 4;
+        config.feature_fraction_bylevel = 0.5;
+        config.feature_fraction_bynode = 0.75; // round(0.75 * 2) = 2: all features of the level
+        let csv: String = (0..500)
+            .map(|i| {
+                let (a, b, c, d) = (i % 11, i % 7, i % 5, i % 3);
+                format!("{},{},{},{},{}\n", a, b, c, d, a * b + c * d)
+            })
+            .collect();
+        let data = Data::from_csv(&config, &csv).unwrap();
+
+        let mut objective = objective_from_name("l2").unwrap();
+        let model = Booster::new(&config, &data, objective.as_mut(), &[]).train();
+
+        for tree in model.trees() {
+            for depth in 0..tree.max_depth() {
+                let mut features: Vec<usize> = (0..tree.nnodes())
+                    .filter(|&n| !tree.is_leaf(n) && tree.depth(n) == depth)
+                    .map(|n| tree.split_crit(n).feature_id)
+                    .collect();
+                features.sort();
+                features.dedup();
+                assert!(features.len() <= 2, "depth {}: {:?}", depth, features);
+            }
+        }
+    }
+
+    #[test]
+    fn goss() {
+        let mut config = Config::new();
+        config.csv_has_header = false;
+        config.niterations = 20;
+        config.learning_rate = 0.5;
+        config.example_sampling = String::from("goss");
+        config.top_rate = 0.2;
+        config.other_rate = 0.2;
+        let csv: String = (0..600)
+            .map(|i| { let (x, y) = (i % 23, i % 7); format!("{},{},{}\n", x, y, x * y) })
+            .collect();
+        let data = Data::from_csv(&config, &csv).unwrap();
+
+        let mut objective = objective_from_name("l2").unwrap();
+        let model = Booster::new(&config, &data, objective.as_mut(), &[]).train();
+
+        let predictions = model.predict(&data);
+        for (&p, &q) in predictions.iter().zip(objective.predictions()) {
+            assert!((p - q).abs() < 1e-3 * (1.0 + p.abs()), "{} vs {}", p, q);
+        }
+        let target = data.get_target();
+        let mse = target.iter().zip(&predictions).map(|(t, p)| (t - p) * (t - p)).sum::<NumT>()
+            / target.len() as NumT;
+        assert!(mse < 1.0, "mse {}", mse); // variance of target is about 1050
+    }
+
+    struct CollectOob<'b>(&'b mut Vec<NumT>);
+
+    impl <'b> TrainCallback for CollectOob<'b> {
+        fn after_iteration(&mut self, info: &IterationInfo) -> CallbackAction {
+            self.0.extend_from_slice(info.oob_metric_values);
+            CallbackAction::Continue
+        }
+    }
+
+    #[test]
+    fn random_forest() {
+        let mut config = Config::new();
+        config.csv_has_header = false;
+        config.boosting = String::from("rf");
+        config.example_replacement = true;
+        config.feature_fraction = 0.5;
+        config.niterations = 10;
+        config.learning_rate = 0.3; // not used
+        let csv: String = (0..200)
+            .map(|i| { let (x, y) = (i % 17, i % 5); format!("{},{},{}\n", x, y, x + 2 * y) })
+            .collect();
+        let data = Data::from_csv(&config, &csv).unwrap();
+        let metrics: Vec<Box<dyn Metric>> = vec![Box::new(crate::metric::Rmse::new())];
+
+        let mut oob = Vec::new();
+        let mut objective = objective_from_name("l2").unwrap();
+        let mut booster = Booster::new(&config, &data, objective.as_mut(), &metrics);
+        booster.add_callback(Box::new(CollectOob(&mut oob)));
+        let model = booster.train();
+
+        let predictions = model.predict(&data);
+        for (&p, &q) in predictions.iter().zip(objective.predictions()) {
+            assert!((p - q).abs() < 1e-3 * (1.0 + p.abs()), "{} vs {}", p, q);
+        }
+
+        // the trees are averaged: predictions stay within the range of the target
+        let (min, max) = data.feat_limits(data.target_id());
+        assert!(predictions.iter().all(|&p| min - 1e-3 <= p && p <= max + 1e-3));
+        let rmse = metrics[0].eval(data.get_target(), &predictions);
+        assert!(rmse < 3.0, "rmse {}", rmse); // stddev of target is 5.6
+
+        // bootstrap: about 1/3 of the examples are out-of-bag for each tree
+        assert_eq!(oob.len(), 10);
+        assert!(oob.iter().all(|&e| e.is_finite() && e > 0.0));
+        assert!(oob[9] > rmse);
+    }
+}
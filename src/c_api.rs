@@ -5,14 +5,16 @@ use std::ptr;
 use std::ffi::CStr;
 
 use libc::{c_int, c_void, c_char};
+use log::{debug, LevelFilter};
 
 use crate::NumT;
 use crate::config::Config;
 use crate::data::Data;
 use crate::tree::AdditiveTree;
 use crate::boost::Booster;
-use crate::objective::objective_from_name;
-use crate::metric::metrics_from_names;
+use crate::checkpoint::Checkpoint;
+use crate::objective::{Objective, objective_from_name};
+use crate::metric::{Metric, metrics_from_names};
 
 
 const NEG1: c_int = -1;
@@ -24,6 +26,8 @@ struct Context {
     nfeatures: usize,
     data: Option<Data>,
     model: Option<AdditiveTree>,
+    custom_objective: Option<CustomObjective>,
+    custom_metrics: Vec<CustomMetric>,
 }
 
 impl Context {
@@ -49,6 +53,8 @@ impl Context {
             nfeatures,
             data: None,
             model: None,
+            custom_objective: None,
+            custom_metrics: Vec::new(),
         });
 
         ptr as *mut Context
@@ -62,9 +68,187 @@ impl Context {
     }
 }
 
+
+
+
+// ------------------------------------------------------------------------------------------------
+
+/// Computes the initial prediction (bias): `(user_data, n, targets, bias_out) -> status`.
+type BiasFn = extern "C" fn(*mut c_void, usize, *const NumT, *mut NumT) -> c_int;
+
+/// Fills the gradients and the gradient bounds (two values, min and max) given the current
+/// predictions: `(user_data, n, targets, predictions, gradients_out, bounds_out) -> status`.
+type GradientFn = extern "C" fn(*mut c_void, usize, *const NumT, *const NumT, *mut NumT,
+                                *mut NumT) -> c_int;
+
+/// Computes the value of a leaf containing the given examples: `(user_data, nleaf_examples,
+/// leaf_examples, n, targets, predictions, gradients, value_out) -> status`.
+type LeafValueFn = extern "C" fn(*mut c_void, usize, *const usize, usize, *const NumT,
+                                 *const NumT, *const NumT, *mut NumT) -> c_int;
+
+/// Evaluates a metric: `(user_data, n, targets, predictions, value_out) -> status`.
+type MetricFn = extern "C" fn(*mut c_void, usize, *const NumT, *const NumT, *mut NumT) -> c_int;
+
+#[derive(Clone, Copy)]
+struct CustomObjective {
+    user_data: *mut c_void,
+    bias_fn: Option<BiasFn>,
+    gradient_fn: GradientFn,
+    leaf_value_fn: Option<LeafValueFn>,
+}
+
+#[derive(Clone)]
+struct CustomMetric {
+    name: String,
+    higher_is_better: bool,
+    user_data: *mut c_void,
+    metric_fn: MetricFn,
+}
+
+/// An objective whose gradients and leaf values are computed by user-supplied callbacks. Without a
+/// `bias_fn`, the bias is 0. Without a `leaf_value_fn`, the leaf value is the mean negative
+/// gradient.
+struct CallbackObjective {
+    funs: CustomObjective,
+    learning_rate: NumT,
+    bias: NumT,
+    bounds: (NumT, NumT),
+    predictions: Vec<NumT>,
+    gradients: Vec<NumT>,
+}
+
+// The objective is moved to the worker threads of the booster's thread pool, where the gradient
+// and leaf value callbacks are invoked. The callbacks are never invoked concurrently: the booster
+// calls the objective from one thread at a time, and `bb_train` blocks until training is done.
+unsafe impl Send for CallbackObjective {}
+unsafe impl Sync for CallbackObjective {}
+
+impl CallbackObjective {
+    fn new(funs: CustomObjective) -> CallbackObjective {
+        CallbackObjective {
+            funs,
+            learning_rate: 0.0,
+            bias: 0.0,
+            bounds: (0.0, 0.0),
+            predictions: Vec::new(),
+            gradients: Vec::new(),
+        }
+    }
+}
+
+impl Objective for CallbackObjective {
+    fn name(&self) -> &'static str   { "Custom" }
+    fn gradients(&self) -> &[NumT]   { &self.gradients }
+    fn predictions(&self) -> &[NumT] { &self.predictions }
+    fn bounds(&self) -> (NumT, NumT) { self.bounds }
+    fn bias(&self) -> NumT           { self.bias }
+
+    fn initialize(&mut self, config: &Config, targets: &[NumT]) {
+        let n = targets.len();
+        let mut bias = 0.0;
+        if let Some(bias_fn) = self.funs.bias_fn {
+            let status = bias_fn(self.funs.user_data, n, targets.as_ptr(), &mut bias);
+            assert_eq!(status, 0, "custom objective: bias callback failed");
+        }
+        self.learning_rate = config.learning_rate;
+        self.bias = bias;
+        self.predictions.clear();
+        self.gradients.clear();
+        self.predictions.resize(n, bias);
+        self.gradients.resize(n, 0.0);
+    }
+
+    /// The bias callback does not see the offsets; the predictions start at `offset + bias`.
+    fn initialize_with_offsets(&mut self, config: &Config, targets: &[NumT], offsets: &[NumT]) {
+        assert_eq!(targets.len(), offsets.len());
+        self.initialize(config, targets);
+        for (p, &o) in self.predictions.iter_mut().zip(offsets) {
+            *p += o;
+        }
+    }
+
+    fn initialize_from_predictions(&mut self, config: &Config, targets: &[NumT],
+                                   predictions: &[NumT]) {
+        assert_eq!(targets.len(), predictions.len());
+        self.initialize(config, targets);
+        self.predictions.copy_from_slice(predictions);
+    }
+
+    fn update(&mut self, targets: &[NumT]) {
+        let n = targets.len();
+        assert_eq!(self.predictions.len(), n);
+        assert_eq!(self.gradients.len(), n);
+
+        let mut bounds = [0.0; 2];
+        let status = (self.funs.gradient_fn)(self.funs.user_data, n, targets.as_ptr(),
+                                             self.predictions.as_ptr(),
+                                             self.gradients.as_mut_ptr(), bounds.as_mut_ptr());
+        assert_eq!(status, 0, "custom objective: gradient callback failed");
+        assert!(bounds[0] < bounds[1], "custom objective: invalid gradient bounds");
+        self.bounds = (bounds[0], bounds[1]);
+    }
+
+    fn predict_leaf_value(&mut self, targets: &[NumT], examples: &[usize]) -> NumT {
+        let mut value = 0.0;
+        if let Some(leaf_value_fn) = self.funs.leaf_value_fn {
+            let status = leaf_value_fn(self.funs.user_data, examples.len(), examples.as_ptr(),
+                                       targets.len(), targets.as_ptr(), self.predictions.as_ptr(),
+                                       self.gradients.as_ptr(), &mut value);
+            assert_eq!(status, 0, "custom objective: leaf value callback failed");
+        } else {
+            for &i in examples { value -= self.gradients[i]; }
+            value /= examples.len() as NumT;
+        }
+        safety_check!(value.is_finite());
+
+        let value = self.learning_rate * value;
+        for &i in examples {
+            self.predictions[i] += value;
+        }
+        value
+    }
+
+    fn update_out_of_bag_prediction(&mut self, i: usize, value: NumT) {
+        self.predictions[i] += value;
+    }
+}
+
+/// A metric evaluated by a user-supplied callback.
+struct CallbackMetric {
+    funs: CustomMetric,
+}
+
+impl Metric for CallbackMetric {
+    fn name(&self) -> &str { &self.funs.name }
+
+    fn eval(&self, targets: &[NumT], predictions: &[NumT]) -> NumT {
+        assert_eq!(targets.len(), predictions.len());
+        let mut value = 0.0;
+        let status = (self.funs.metric_fn)(self.funs.user_data, targets.len(), targets.as_ptr(),
+                                           predictions.as_ptr(), &mut value);
+        assert_eq!(status, 0, "custom metric {}: callback failed", self.funs.name);
+        value
+    }
+
+    fn higher_is_better(&self) -> bool { self.funs.higher_is_better }
+}
+
+
+
+
+// ------------------------------------------------------------------------------------------------
+
+/// Install the logger on first use, and apply the `verbosity` of the current configuration.
+fn init_logger(config: &Config) {
+    let _ = pretty_env_logger::formatted_builder()
+        .filter_level(LevelFilter::Debug)
+        .try_init();
+    log::set_max_level(config.log_level_filter());
+}
+
 impl Drop for Context {
     fn drop(&mut self) {
-        println!("Rust: dropping Context {:p}", self as *const Context);
+        debug!("Rust: dropping Context {:p}", self as *const Context);
     }
 }
 
@@ -108,14 +292,14 @@ wrap_catch_unwind!(
         assert!(nfeatures > 0);
         unsafe {
             let ptr = Context::alloc(nfeatures as usize);
-            println!("Rust: alloc Context {:?}", ptr);
+            debug!("Rust: alloc Context {:?}", ptr);
             ptr as *mut c_void
         }
     }
 
     /// Call this to clean up your `*const Context` pointer.
     fn bb_dealloc(ptr: *mut c_void) -> c_int | NEG1 {
-        println!("Rust: dealloc Context {:?}", ptr);
+        debug!("Rust: dealloc Context {:?}", ptr);
         unsafe {
             Context::dealloc(ptr as *mut Context);
             0
@@ -123,10 +307,9 @@ wrap_catch_unwind!(
     }
 
     /// Allocate memory for data, deallocate previously allocated memory for data.
-    fn bb_refresh_data(ptr: *mut c_void, nexamples: c_int) -> c_int | NEG1 {
+    fn bb_refresh_data(ptr: *mut c_void, nexamples: usize) -> c_int | NEG1 {
         assert!(nexamples > 0);
         unsafe {
-            let nexamples = nexamples as usize;
             let context = Context::from_raw_ptr_mut(ptr);
             context.data = Some(Data::empty(&context.config, context.nfeatures, nexamples));
         }
@@ -152,29 +335,104 @@ wrap_catch_unwind!(
         0
     }
 
-    /// Set a single config field.
+    /// Set a single config field. `max_nbins` cannot change once data is loaded.
     fn bb_set_config_field(ptr: *mut c_void, name: *const c_char, value: *const c_char)
         -> c_int | NEG1
     {
         unsafe {
             let context = Context::from_raw_ptr_mut(ptr);
-            assert!(context.data.is_none(), "set options before loading data");
             let name = CStr::from_ptr(name).to_str().expect("invalid utf-8 in key");
             let value = CStr::from_ptr(value).to_str().expect("invalid utf-8 in value");
+            let max_nbins = context.config.max_nbins;
             context.config.parse_record(name, value).unwrap();
+            assert!(context.data.is_none() || context.config.max_nbins == max_nbins,
+                    "set max_nbins before loading data");
         }
         0
     }
     
+    /// Set the initial score (base margin) of each example of the current data. Training starts
+    /// from these scores, and `bb_predict` adds them to the model's predictions.
+    fn bb_set_init_scores(ptr: *mut c_void, init_scores: *const NumT) -> c_int | NEG1 {
+        unsafe {
+            let context = Context::from_raw_ptr_mut(ptr);
+            let data = context.data.as_mut().expect("no data: use bb_refresh_data first");
+            let init_scores = slice::from_raw_parts(init_scores, data.nexamples());
+            data.set_init_scores(init_scores);
+        }
+        0
+    }
+
+    /// Use a custom objective implemented by the given callbacks instead of the `objective` config
+    /// field. `bias_fn` and `leaf_value_fn` may be null. `user_data` is passed to each callback.
+    /// The callbacks are invoked one at a time, but not necessarily from the thread that called
+    /// `bb_train`: the gradients and leaf values are computed on a thread of the training pool.
+    fn bb_set_custom_objective(ptr: *mut c_void, user_data: *mut c_void, bias_fn: Option<BiasFn>,
+                               gradient_fn: Option<GradientFn>,
+                               leaf_value_fn: Option<LeafValueFn>)
+        -> c_int | NEG1
+    {
+        unsafe {
+            let context = Context::from_raw_ptr_mut(ptr);
+            let gradient_fn = gradient_fn.expect("custom objective: gradient_fn is required");
+            context.custom_objective = Some(CustomObjective {
+                user_data,
+                bias_fn,
+                gradient_fn,
+                leaf_value_fn,
+            });
+        }
+        0
+    }
+
+    /// Evaluate a custom metric implemented by the given callback during training, in addition to
+    /// the metrics in the `metrics` config field.
+    fn bb_add_custom_metric(ptr: *mut c_void, name: *const c_char, higher_is_better: c_int,
+                            user_data: *mut c_void, metric_fn: Option<MetricFn>)
+        -> c_int | NEG1
+    {
+        unsafe {
+            if name.is_null() { return NEG1; }
+            let context = Context::from_raw_ptr_mut(ptr);
+            let name = CStr::from_ptr(name).to_str().expect("invalid utf-8 in name");
+            let metric_fn = metric_fn.expect("custom metric: metric_fn is required");
+            context.custom_metrics.push(CustomMetric {
+                name: name.to_string(),
+                higher_is_better: higher_is_better != 0,
+                user_data,
+                metric_fn,
+            });
+        }
+        0
+    }
+
     /// Train a model on the data.
     fn bb_train(ptr: *mut c_void) -> c_int | NEG1 {
         let context = unsafe { Context::from_raw_ptr_mut(ptr) };
+        init_logger(&context.config);
 
         let data = context.data.as_mut().expect("no data: use bb_refresh_data first");
-        let mut objective = objective_from_name(&context.config.objective)
-            .expect("unknown objective");
-        let metrics = metrics_from_names(&context.config.metrics).expect("unknown metric");
-        let booster = Booster::new(&context.config, data, objective.as_mut(), &metrics);
+        let mut objective: Box<dyn Objective> = match context.custom_objective {
+            Some(funs) => Box::new(CallbackObjective::new(funs)),
+            None => objective_from_name(&context.config.objective).expect("unknown objective"),
+        };
+        let mut metrics = metrics_from_names(&context.config.metrics, &context.config)
+            .expect("unknown metric");
+        for funs in &context.custom_metrics {
+            metrics.push(Box::new(CallbackMetric { funs: funs.clone() }));
+        }
+        let booster = match context.model.take() {
+            _ if !context.config.resume_from.is_empty() => {
+                let checkpoint = Checkpoint::load(&context.config.resume_from)
+                    .expect("cannot load checkpoint");
+                Booster::resume(&context.config, data, objective.as_mut(), &metrics, checkpoint)
+                    .expect("cannot resume from checkpoint")
+            },
+            Some(model) if context.config.warm_start => {
+                Booster::warm_start(&context.config, data, objective.as_mut(), &metrics, model)
+            },
+            _ => Booster::new(&context.config, data, objective.as_mut(), &metrics),
+        };
         context.model = Some(booster.train());
         0
     }
@@ -192,3 +450,139 @@ wrap_catch_unwind!(
         0
     }
 );
+
+
+
+
+
+
+
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct Calls {
+        bias: AtomicUsize,
+        gradient: AtomicUsize,
+        leaf_value: AtomicUsize,
+        metric: AtomicUsize,
+    }
+
+    fn calls<'a>(user_data: *mut c_void) -> &'a Calls { unsafe { &*(user_data as *const Calls) } }
+
+    // The callbacks below compute the l2 objective and metric, like `objective::L2`.
+
+    extern "C" fn l2_bias(user_data: *mut c_void, n: usize, targets: *const NumT,
+                          bias_out: *mut NumT) -> c_int {
+        calls(user_data).bias.fetch_add(1, Ordering::SeqCst);
+        let targets = unsafe { slice::from_raw_parts(targets, n) };
+        unsafe { *bias_out = targets.iter().fold(0.0, |x, y| x+y) / n as NumT; }
+        0
+    }
+
+    extern "C" fn l2_gradient(user_data: *mut c_void, n: usize, targets: *const NumT,
+                              predictions: *const NumT, gradients_out: *mut NumT,
+                              bounds_out: *mut NumT) -> c_int {
+        calls(user_data).gradient.fetch_add(1, Ordering::SeqCst);
+        let (targets, predictions, gradients, bounds) = unsafe {
+            (slice::from_raw_parts(targets, n), slice::from_raw_parts(predictions, n),
+             slice::from_raw_parts_mut(gradients_out, n), slice::from_raw_parts_mut(bounds_out, 2))
+        };
+        let (mut min, mut max) = (NumT::INFINITY, NumT::NEG_INFINITY);
+        for i in 0..n {
+            let residual = targets[i] - predictions[i];
+            min = min.min(residual);
+            max = max.max(residual);
+            gradients[i] = -residual;
+        }
+        let bound = NumT::min(min.abs(), max.abs());
+        bounds[0] = -bound;
+        bounds[1] = bound;
+        0
+    }
+
+    extern "C" fn l2_leaf_value(user_data: *mut c_void, nleaf_examples: usize,
+                                leaf_examples: *const usize, n: usize, targets: *const NumT,
+                                predictions: *const NumT, _gradients: *const NumT,
+                                value_out: *mut NumT) -> c_int {
+        calls(user_data).leaf_value.fetch_add(1, Ordering::SeqCst);
+        let (examples, targets, predictions) = unsafe {
+            (slice::from_raw_parts(leaf_examples, nleaf_examples),
+             slice::from_raw_parts(targets, n), slice::from_raw_parts(predictions, n))
+        };
+        let mut sum = 0.0;
+        for &i in examples {
+            sum += targets[i] - predictions[i];
+        }
+        unsafe { *value_out = sum / nleaf_examples as NumT; }
+        0
+    }
+
+    extern "C" fn l2_metric(user_data: *mut c_void, n: usize, targets: *const NumT,
+                            predictions: *const NumT, value_out: *mut NumT) -> c_int {
+        calls(user_data).metric.fetch_add(1, Ordering::SeqCst);
+        let (targets, predictions) = unsafe {
+            (slice::from_raw_parts(targets, n), slice::from_raw_parts(predictions, n))
+        };
+        let sum = targets.iter().zip(predictions).map(|(t, p)| (t - p) * (t - p)).sum::<NumT>();
+        unsafe { *value_out = sum / n as NumT; }
+        0
+    }
+
+    /// Train through the C API on a small dataset, and return the predictions on that dataset.
+    fn train(calls: Option<&Calls>) -> Vec<NumT> {
+        let n = 500;
+        let x0: Vec<NumT> = (0..n).map(|i| (i % 23) as NumT).collect();
+        let x1: Vec<NumT> = (0..n).map(|i| (i % 7) as NumT).collect();
+        let y: Vec<NumT> = x0.iter().zip(&x1).map(|(a, b)| a * b).collect();
+
+        let ptr = bb_alloc(2);
+        let field = |name: &str, value: &str| {
+            let (name, value) = (std::ffi::CString::new(name).unwrap(),
+                                 std::ffi::CString::new(value).unwrap());
+            assert_eq!(bb_set_config_field(ptr, name.as_ptr(), value.as_ptr()), 0);
+        };
+        field("niterations", "5");
+        field("verbosity", "0");
+        if let Some(calls) = calls {
+            let user_data = calls as *const Calls as *mut c_void;
+            assert_eq!(bb_set_custom_objective(ptr, user_data, Some(l2_bias), Some(l2_gradient),
+                                               Some(l2_leaf_value)), 0);
+            let name = std::ffi::CString::new("custom_l2").unwrap();
+            assert_eq!(bb_add_custom_metric(ptr, name.as_ptr(), 0, user_data, Some(l2_metric)),
+                       0);
+            assert_eq!(bb_add_custom_metric(ptr, ptr::null(), 0, user_data, Some(l2_metric)),
+                       NEG1);
+        } else {
+            field("objective", "l2");
+        }
+        assert_eq!(bb_refresh_data(ptr, n), 0);
+        for (feat_id, column) in [&x0, &x1, &y].iter().enumerate() {
+            assert_eq!(bb_set_feature_data(ptr, feat_id as c_int, column.as_ptr(), 0), 0);
+        }
+        assert_eq!(bb_train(ptr), 0);
+        let mut predictions = vec![0.0; n];
+        assert_eq!(bb_predict(ptr, predictions.as_mut_ptr()), 0);
+        assert_eq!(bb_dealloc(ptr), 0);
+        predictions
+    }
+
+    #[test]
+    fn custom_objective_and_metric() {
+        let calls = Calls::default();
+        let custom = train(Some(&calls));
+        let l2 = train(None);
+
+        assert_eq!(calls.bias.load(Ordering::SeqCst), 1);
+        assert_eq!(calls.gradient.load(Ordering::SeqCst), 5);
+        assert!(calls.leaf_value.load(Ordering::SeqCst) >= 5);
+        assert_eq!(calls.metric.load(Ordering::SeqCst), 5);
+        assert_eq!(custom, l2);
+        assert!(custom.iter().any(|&p| p != custom[0]));
+    }
+}
@@ -0,0 +1,304 @@
+/*
+ * Copyright 2019 DTAI Research Group - KU Leuven.
+ * License: Apache License 2.0
+ * Author: Laurens Devos
+*/
+
+use std::io::Write;
+
+use log::{info, warn};
+
+use crate::NumT;
+use crate::tree::Tree;
+use crate::metric::Metric;
+
+/// What the booster should do after a callback has been invoked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallbackAction {
+    Continue,
+    Stop,
+}
+
+/// Information about a single boosting iteration, passed to each `TrainCallback`.
+pub struct IterationInfo<'a> {
+    /// Iteration count, starting at 1.
+    pub iteration: usize,
+
+    /// The tree constructed in this iteration.
+    pub tree: &'a Tree,
+
+    /// Time spent updating the objective, updating the dataset and learning the tree, and the
+    /// total time since the start of training (all in seconds).
+    pub objective_time: f32,
+    pub dataset_time: f32,
+    pub tree_time: f32,
+    pub total_time: f32,
+
+    /// The number of gradients outside the objective's gradient bounds, which are clipped by the
+    /// discretization.
+    pub nclipped: usize,
+
+    /// The metrics of the booster, and their values on the training data. `metric_values` is empty
+    /// in iterations where the metrics are not evaluated (see `metric_frequency`).
+    pub metrics: &'a [Box<dyn Metric>],
+    pub metric_values: &'a [NumT],
+
+    /// Random forest: the values of the metrics on the out-of-bag examples (empty otherwise).
+    pub oob_metric_values: &'a [NumT],
+}
+
+/// Hook invoked by the `Booster` after each iteration.
+pub trait TrainCallback {
+    fn after_iteration(&mut self, info: &IterationInfo) -> CallbackAction;
+}
+
+
+
+
+// ------------------------------------------------------------------------------------------------
+
+/// Prints the timings and the metric values of each iteration.
+pub struct PrintProgress {}
+
+impl PrintProgress {
+    pub fn new() -> PrintProgress { PrintProgress {} }
+}
+
+impl TrainCallback for PrintProgress {
+    fn after_iteration(&mut self, info: &IterationInfo) -> CallbackAction {
+        info!("[{:3}] timings: objective {:5.1}, dataset {:5.1}, tree {:5.1} ms, total {:.3} s",
+              info.iteration, info.objective_time * 1000.0, info.dataset_time * 1000.0,
+              info.tree_time * 1000.0, info.total_time);
+        info!("[   ] clipped gradients {}", info.nclipped);
+        for (m, eval) in info.metrics.iter().zip(info.metric_values) {
+            info!("[   ] eval {:<13} {:10.4e}", m.name(), eval);
+        }
+        for (m, eval) in info.metrics.iter().zip(info.oob_metric_values) {
+            info!("[   ] oob  {:<13} {:10.4e}", m.name(), eval);
+        }
+        CallbackAction::Continue
+    }
+}
+
+
+
+
+// ------------------------------------------------------------------------------------------------
+
+/// Stops training when the first metric has not improved during `patience` consecutive
+/// evaluations. The metrics are evaluated on the training data.
+pub struct EarlyStopping {
+    patience: usize,
+    best_value: Option<NumT>,
+    best_iteration: usize,
+    nevals_without_improvement: usize,
+}
+
+impl EarlyStopping {
+    pub fn new(patience: usize) -> EarlyStopping {
+        assert!(patience > 0);
+        EarlyStopping {
+            patience,
+            best_value: None,
+            best_iteration: 0,
+            nevals_without_improvement: 0,
+        }
+    }
+
+    /// The iteration at which the first metric had its best value.
+    pub fn best_iteration(&self) -> usize { self.best_iteration }
+}
+
+impl TrainCallback for EarlyStopping {
+    fn after_iteration(&mut self, info: &IterationInfo) -> CallbackAction {
+        let (metric, &value) = match (info.metrics.first(), info.metric_values.first()) {
+            (Some(m), Some(v)) => (m, v),
+            _ => return CallbackAction::Continue, // metrics not evaluated this iteration
+        };
+
+        let improved = match self.best_value {
+            None => true,
+            Some(best) if metric.higher_is_better() => value > best,
+            Some(best) => value < best,
+        };
+
+        if improved {
+            self.best_value = Some(value);
+            self.best_iteration = info.iteration;
+            self.nevals_without_improvement = 0;
+            CallbackAction::Continue
+        } else {
+            self.nevals_without_improvement += 1;
+            if self.nevals_without_improvement < self.patience {
+                CallbackAction::Continue
+            } else {
+                info!("[   ] early stopping: {} did not improve since iteration {}",
+                      metric.name(), self.best_iteration);
+                CallbackAction::Stop
+            }
+        }
+    }
+}
+
+
+
+
+// ------------------------------------------------------------------------------------------------
+
+/// Writes a JSON-lines training log: one JSON object with the timings (in milliseconds, total in
+/// seconds), the number of clipped gradients and the evaluated metric values per iteration, and
+/// for random forests, the metric values on the out-of-bag examples.
+pub struct JsonLog<W: Write> {
+    writer: W,
+}
+
+impl <W: Write> JsonLog<W> {
+    pub fn new(writer: W) -> JsonLog<W> { JsonLog { writer } }
+
+    pub fn into_inner(self) -> W { self.writer }
+
+    fn write_line(&mut self, info: &IterationInfo) -> std::io::Result<()> {
+        let w = &mut self.writer;
+        write!(w, "{{\"iteration\":{},\"objective_ms\":{},\"dataset_ms\":{},\"tree_ms\":{},\
+                   \"total_s\":{},\"nclipped\":{},\"metrics\":",
+               info.iteration, info.objective_time * 1000.0, info.dataset_time * 1000.0,
+               info.tree_time * 1000.0, info.total_time, info.nclipped)?;
+        write_json_metrics(w, info.metrics, info.metric_values)?;
+        if !info.oob_metric_values.is_empty() {
+            write!(w, ",\"oob_metrics\":")?;
+            write_json_metrics(w, info.metrics, info.oob_metric_values)?;
+        }
+        writeln!(w, "}}")?;
+        w.flush()
+    }
+}
+
+impl <W: Write> TrainCallback for JsonLog<W> {
+    fn after_iteration(&mut self, info: &IterationInfo) -> CallbackAction {
+        if let Err(err) = self.write_line(info) {
+            warn!("training log write error: {}", err);
+        }
+        CallbackAction::Continue
+    }
+}
+
+fn write_json_metrics<W: Write>(w: &mut W, metrics: &[Box<dyn Metric>], values: &[NumT])
+    -> std::io::Result<()>
+{
+    write!(w, "{{")?;
+    for (i, (m, eval)) in metrics.iter().zip(values).enumerate() {
+        if i > 0 { write!(w, ",")?; }
+        write_json_str(w, m.name())?;
+        if eval.is_finite() { write!(w, ":{}", eval)?; }
+        else                { write!(w, ":null")?; }
+    }
+    write!(w, "}}")
+}
+
+fn write_json_str<W: Write>(w: &mut W, s: &str) -> std::io::Result<()> {
+    write!(w, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"'  => write!(w, "\\\"")?,
+            '\\' => write!(w, "\\\\")?,
+            c if (c as u32) < 0x20 => write!(w, "\\u{:04x}", c as u32)?,
+            c => write!(w, "{}", c)?,
+        }
+    }
+    write!(w, "\"")
+}
+
+
+
+
+
+
+
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::metric::{Rmse, Accuracy};
+
+    fn info<'a>(tree: &'a Tree, metrics: &'a [Box<dyn Metric>], values: &'a [NumT])
+        -> IterationInfo<'a>
+    {
+        IterationInfo {
+            iteration: 0,
+            tree,
+            objective_time: 0.0,
+            dataset_time: 0.0,
+            tree_time: 0.0,
+            total_time: 0.0,
+            nclipped: 0,
+            metrics,
+            metric_values: values,
+            oob_metric_values: &[],
+        }
+    }
+
+    #[test]
+    fn early_stopping() {
+        let tree = Tree::new(1, Vec::new());
+        let metrics: Vec<Box<dyn Metric>> = vec![Box::new(Rmse::new())];
+        let mut es = EarlyStopping::new(2);
+
+        for (i, &(v, action)) in [(1.0, CallbackAction::Continue),
+                                  (0.5, CallbackAction::Continue),
+                                  (0.6, CallbackAction::Continue),
+                                  (0.4, CallbackAction::Continue),
+                                  (0.4, CallbackAction::Continue),
+                                  (0.5, CallbackAction::Stop)].iter().enumerate() {
+            let values = [v];
+            let mut info = info(&tree, &metrics, &values);
+            info.iteration = i + 1;
+            assert_eq!(es.after_iteration(&info), action);
+        }
+        assert_eq!(es.best_iteration(), 4);
+
+        // metrics not evaluated
+        assert_eq!(es.after_iteration(&info(&tree, &metrics, &[])), CallbackAction::Continue);
+    }
+
+    #[test]
+    fn early_stopping_higher_is_better() {
+        let tree = Tree::new(1, Vec::new());
+        let metrics: Vec<Box<dyn Metric>> = vec![Box::new(Accuracy::new(0.5))];
+        let mut es = EarlyStopping::new(1);
+        assert_eq!(es.after_iteration(&info(&tree, &metrics, &[0.5])), CallbackAction::Continue);
+        assert_eq!(es.after_iteration(&info(&tree, &metrics, &[0.6])), CallbackAction::Continue);
+        assert_eq!(es.after_iteration(&info(&tree, &metrics, &[0.6])), CallbackAction::Stop);
+    }
+
+    #[test]
+    fn json_log() {
+        let tree = Tree::new(1, Vec::new());
+        let metrics: Vec<Box<dyn Metric>> = vec![Box::new(Rmse::new()),
+                                                 Box::new(Accuracy::new(0.5))];
+        let mut log = JsonLog::new(Vec::new());
+
+        let mut info1 = info(&tree, &metrics, &[0.25, 0.5]);
+        info1.iteration = 1;
+        info1.tree_time = 0.002;
+        info1.total_time = 1.5;
+        info1.nclipped = 3;
+        let mut info2 = info(&tree, &metrics, &[]);
+        info2.iteration = 2;
+        assert_eq!(log.after_iteration(&info1), CallbackAction::Continue);
+        assert_eq!(log.after_iteration(&info2), CallbackAction::Continue);
+
+        let out = String::from_utf8(log.into_inner()).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "{\"iteration\":1,\"objective_ms\":0,\"dataset_ms\":0,\"tree_ms\":2,\
+                              \"total_s\":1.5,\"nclipped\":3,\
+                              \"metrics\":{\"Rmse\":0.25,\"Accuracy\":0.5}}");
+        assert!(lines[1].ends_with("\"metrics\":{}}"));
+
+        let mut buf = Vec::new();
+        write_json_str(&mut buf, "a\"b\\c\n").unwrap();
+        assert_eq!(&buf[..], &b"\"a\\\"b\\\\c\\u000a\""[..]);
+    }
+}
@@ -0,0 +1,239 @@
+/*
+ * Copyright 2019 DTAI Research Group - KU Leuven.
+ * License: Apache License 2.0
+ * Author: Laurens Devos
+*/
+
+use std::io::{Read, Write, BufReader, BufWriter, Result as IoResult, Error, ErrorKind};
+use std::fs::{self, File};
+use std::path::Path;
+
+use crate::NumT;
+use crate::tree::AdditiveTree;
+use crate::dataset::DatasetCheckpoint;
+
+const MAGIC: u64 = u64::from_le_bytes(*b"BBCKPT02");
+
+/// Little-endian binary encoding of the values in a checkpoint.
+pub struct CheckpointWriter<W: Write> {
+    writer: W,
+}
+
+impl <W: Write> CheckpointWriter<W> {
+    pub fn new(writer: W) -> Self { CheckpointWriter { writer } }
+    pub fn into_inner(self) -> W { self.writer }
+
+    pub fn write_u64(&mut self, x: u64) -> IoResult<()> { self.writer.write_all(&x.to_le_bytes()) }
+    pub fn write_usize(&mut self, x: usize) -> IoResult<()> { self.write_u64(x as u64) }
+    pub fn write_bool(&mut self, x: bool) -> IoResult<()> { self.write_u64(x as u64) }
+    pub fn write_numt(&mut self, x: NumT) -> IoResult<()> { self.write_u64(x.to_bits() as u64) }
+
+    pub fn write_usizes(&mut self, xs: &[usize]) -> IoResult<()> {
+        self.write_usize(xs.len())?;
+        for &x in xs { self.write_usize(x)?; }
+        Ok(())
+    }
+
+    pub fn write_numts(&mut self, xs: &[NumT]) -> IoResult<()> {
+        self.write_usize(xs.len())?;
+        for &x in xs { self.write_numt(x)?; }
+        Ok(())
+    }
+}
+
+pub struct CheckpointReader<R: Read> {
+    reader: R,
+}
+
+impl <R: Read> CheckpointReader<R> {
+    pub fn new(reader: R) -> Self { CheckpointReader { reader } }
+
+    pub fn read_u64(&mut self) -> IoResult<u64> {
+        let mut buf = [0u8; 8];
+        self.reader.read_exact(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    pub fn read_usize(&mut self) -> IoResult<usize> { self.read_u64().map(|x| x as usize) }
+    pub fn read_bool(&mut self) -> IoResult<bool> { self.read_u64().map(|x| x != 0) }
+    pub fn read_numt(&mut self) -> IoResult<NumT> { self.read_u64().map(|x| NumT::from_bits(x as _)) }
+
+    pub fn read_usizes(&mut self) -> IoResult<Vec<usize>> {
+        let n = self.read_usize()?;
+        (0..n).map(|_| self.read_usize()).collect()
+    }
+
+    pub fn read_numts(&mut self) -> IoResult<Vec<NumT>> {
+        let n = self.read_usize()?;
+        (0..n).map(|_| self.read_numt()).collect()
+    }
+}
+
+pub fn invalid_data(msg: &str) -> Error { Error::new(ErrorKind::InvalidData, msg) }
+
+
+
+
+// ------------------------------------------------------------------------------------------------
+
+/// Snapshot of the state of a `Booster` after `iter_count` iterations. Resuming from a checkpoint
+/// gives bit-identical results to an uninterrupted run with the same configuration and data. The
+/// state of callbacks (e.g. early stopping) is not part of a checkpoint.
+pub struct Checkpoint {
+    pub iter_count: usize,
+    pub rng_ndraws: u64, // draws of the `Booster`'s random number generator
+    pub discr_nbits: usize, // raised by the quantization error threshold
+    pub ensemble: AdditiveTree,
+    pub predictions: Vec<NumT>,
+    pub oob_sums: Vec<NumT>,     // random forest out-of-bag state, empty otherwise
+    pub oob_counts: Vec<usize>,
+    pub dataset: DatasetCheckpoint,
+}
+
+impl Checkpoint {
+    pub fn write<W: Write>(&self, w: &mut CheckpointWriter<W>) -> IoResult<()> {
+        w.write_u64(MAGIC)?;
+        w.write_usize(self.iter_count)?;
+        w.write_u64(self.rng_ndraws)?;
+        w.write_usize(self.discr_nbits)?;
+        self.ensemble.write_checkpoint(w)?;
+        w.write_numts(&self.predictions)?;
+        w.write_numts(&self.oob_sums)?;
+        w.write_usizes(&self.oob_counts)?;
+        self.dataset.write(w)
+    }
+
+    pub fn read<R: Read>(r: &mut CheckpointReader<R>) -> IoResult<Checkpoint> {
+        if r.read_u64()? != MAGIC { return Err(invalid_data("not a bitboost checkpoint")); }
+        Ok(Checkpoint {
+            iter_count: r.read_usize()?,
+            rng_ndraws: r.read_u64()?,
+            discr_nbits: r.read_usize()?,
+            ensemble: AdditiveTree::read_checkpoint(r)?,
+            predictions: r.read_numts()?,
+            oob_sums: r.read_numts()?,
+            oob_counts: r.read_usizes()?,
+            dataset: DatasetCheckpoint::read(r)?,
+        })
+    }
+
+    /// Write the checkpoint to a temporary file first and then rename it, so that an interrupted
+    /// write never replaces a previous valid checkpoint.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        let file = File::create(&tmp).map_err(|err| format!("checkpoint error: {}", err))?;
+        let mut w = CheckpointWriter::new(BufWriter::new(file));
+        self.write(&mut w)
+            .and_then(|_| w.into_inner().flush())
+            .map_err(|err| format!("checkpoint write error: {}", err))?;
+        fs::rename(&tmp, path).map_err(|err| format!("checkpoint error: {}", err))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Checkpoint, String> {
+        let file = File::open(path).map_err(|err| format!("checkpoint error: {}", err))?;
+        let mut r = CheckpointReader::new(BufReader::new(file));
+        Checkpoint::read(&mut r).map_err(|err| format!("checkpoint read error: {}", err))
+    }
+}
+
+
+
+
+
+
+
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use crate::data::Data;
+    use crate::boost::Booster;
+    use crate::objective::objective_from_name;
+
+    fn make_data(config: &Config) -> Data {
+        let mut csv = String::new();
+        let mut state = 17u32;
+        for _ in 0..500 {
+            let mut rnd = || { state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                               (state >> 8) as NumT / (1 << 24) as NumT };
+            let (a, b, c) = (rnd(), rnd(), (rnd() * 5.0).floor());
+            csv.push_str(&format!("{},{},{},{}\n", a, b, c, a * 2.0 - b + c * 0.3));
+        }
+        Data::from_csv(config, &csv).unwrap()
+    }
+
+    fn resume_is_bit_identical(boosting: &str, adjust: fn(&mut Config)) {
+        let path = std::env::temp_dir().join(format!("bitboost_ckpt_{}_{}", boosting,
+                                                     std::process::id()));
+        let new_config = || {
+            let mut config = Config::new();
+            config.boosting = boosting.to_string();
+            config.csv_has_header = false;
+            config.categorical_features = vec![2];
+            config.niterations = 10;
+            config.learning_rate = 0.5;
+            config.example_fraction = 0.7;
+            config.feature_fraction = 0.67;
+            config.feature_fraction_bynode = 0.5;
+            config.sample_freq = 3;
+            config.random_seed = 9;
+            adjust(&mut config);
+            config
+        };
+        let config = new_config();
+        let data = make_data(&config);
+
+        let mut objective = objective_from_name("l2").unwrap();
+        let full = Booster::new(&config, &data, objective.as_mut(), &[]).train();
+
+        // interrupted run: write a checkpoint after 4 iterations, stop after 5
+        let mut config1 = new_config();
+        config1.niterations = 5;
+        config1.checkpoint_frequency = 4;
+        config1.checkpoint_path = path.to_str().unwrap().to_string();
+        let mut objective = objective_from_name("l2").unwrap();
+        Booster::new(&config1, &data, objective.as_mut(), &[]).train();
+
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(checkpoint.iter_count, 4);
+        assert_eq!(checkpoint.ensemble.ntrees(), 4);
+
+        let mut objective = objective_from_name("l2").unwrap();
+        let resumed = Booster::resume(&config, &data, objective.as_mut(), &[], checkpoint)
+            .unwrap().train();
+
+        assert_eq!(resumed.ntrees(), 10);
+        let (p0, p1) = (full.predict(&data), resumed.predict(&data));
+        assert!(p0.iter().zip(&p1).all(|(x, y)| x.to_bits() == y.to_bits()));
+    }
+
+    #[test]
+    fn resume_gbdt() { resume_is_bit_identical("gbdt", |_| {}); }
+
+    #[test]
+    fn resume_dart() { resume_is_bit_identical("dart", |_| {}); }
+
+    #[test]
+    fn resume_rf() { resume_is_bit_identical("rf", |_| {}); }
+
+    #[test]
+    fn resume_adaptive_discr_nbits() {
+        resume_is_bit_identical("gbdt", |config| {
+            config.discr_nbits = 1;
+            config.discr_nbits_schedule = vec![6];
+            config.discr_nbits_error_threshold = 0.8;
+            config.stochastic_rounding = true;
+        });
+    }
+
+    #[test]
+    fn invalid_checkpoint() {
+        let mut r = CheckpointReader::new(&b"BBCKPT00........"[..]);
+        assert!(Checkpoint::read(&mut r).is_err());
+    }
+}
@@ -7,6 +7,8 @@
 use std::io::{Write, Error};
 use std::str::FromStr;
 
+use log::LevelFilter;
+
 use crate::NumT;
 
 
@@ -89,6 +91,10 @@ macro_rules! parse_config {
 }
 
 fn parse_fromstr<T: FromStr>(value: &str) -> Option<T> { value.parse::<T>().ok() }
+fn parse_vec_vec<T: FromStr>(value: &str) -> Option<Vec<Vec<T>>> {
+    if value.is_empty() { Some(Vec::new()) }
+    else { value.split(';').map(|s| parse_vec(s.trim())).collect() }
+}
 fn parse_vec<T: FromStr>(value: &str) -> Option<Vec<T>> {
     let mut res = Vec::new();
     if value.is_empty() { Some(res) }
@@ -122,13 +128,46 @@ parse_config!(Config,
     /// Name of the objective to use (l2, l1, huber, binary, hinge).
     objective: String = String::from("L2"),         parse_fromstr;
 
-    /// Comma separated list of metrics to evaluate during training (l2, rmse, binary_loss,
-    /// binary_error).
+    /// Boosting type: gbdt for gradient boosting, dart for gradient boosting with dropout, rf for
+    /// a random forest (each tree fits the target, predictions are averaged; requires example
+    /// sampling, see `example_fraction` and `example_replacement`).
+    boosting: String = String::from("gbdt"),        parse_fromstr;
+
+    /// DART: probability of dropping each of the existing trees in an iteration.
+    dart_drop_rate: NumT = 0.1,                     parse_fromstr;
+
+    /// DART: probability of dropping no trees at all in an iteration.
+    dart_skip_rate: NumT = 0.5,                     parse_fromstr;
+
+    /// DART: weight normalization of the new and the dropped trees (tree, forest). With tree,
+    /// the new tree gets the same weight as each dropped tree; with forest, the new tree gets the
+    /// same weight as all dropped trees together.
+    dart_normalize: String = String::from("tree"),  parse_fromstr;
+
+    /// Comma separated list of metrics to evaluate during training (l2, rmse, mae, mape, r2,
+    /// binary_loss, binary_error, binary_error01, logloss, accuracy, f1, auc, pr_auc).
     metrics: Vec<String> = vec![],                  parse_vec;
 
+    /// Decision threshold on the predicted values for the accuracy and f1 metrics. Use 0.0 for the
+    /// raw scores of the binary objective, 0.5 for probabilities.
+    metric_threshold: NumT = 0.5,                   parse_fromstr;
+
     /// The metrics are evaluated every `metric_frequency` iterations.
     metric_frequency: usize = 1,                    parse_fromstr;
 
+    /// Stop training when the first metric in `metrics` has not improved for
+    /// `early_stopping_rounds` consecutive evaluations (0 disables early stopping). The metric is
+    /// evaluated on the training data. With gbdt, the trees after the best iteration are removed.
+    early_stopping_rounds: usize = 0,               parse_fromstr;
+
+    /// Amount of output during training: 0 only prints warnings and errors, 1 also prints the
+    /// progress of each iteration, 2 also prints debug information.
+    verbosity: usize = 1,                           parse_fromstr;
+
+    /// Path of a file to which a JSON-lines training log is written: one JSON object with the
+    /// timings and metric values per iteration. No log is written when empty.
+    training_log: String = String::new(),           parse_fromstr;
+
     /// Whether first line of CSV file is header (cli only).
     csv_has_header: bool = true,                    parse_fromstr;
     
@@ -138,9 +177,39 @@ parse_config!(Config,
     /// Comma separated list of categorical feature indexes (starting at 0).
     categorical_features: Vec<usize> = vec![],      parse_vec;
 
+    /// Comma separated list of monotone constraints, one per feature: 1 for an increasing, -1 for
+    /// a decreasing relation between a numerical feature and the prediction, 0 for no constraint.
+    /// Missing values at the end of the list are 0.
+    monotone_constraints: Vec<isize> = vec![],      parse_vec;
+
+    /// Groups of features that are allowed to interact, separated by semicolons, e.g. `0,1;1,2,3`.
+    /// All features used on a path from the root to a leaf must belong to a single group. Features
+    /// that are not in any group can only be used on paths without other features. No constraints
+    /// when empty.
+    interaction_constraints: Vec<Vec<usize>> = vec![], parse_vec_vec;
+
+    /// Index of the CSV column containing the initial score (base margin) of each example, or -1
+    /// if there is none. This column is not used as a feature (cli only).
+    init_score_column: isize = -1,                  parse_fromstr;
+
     /// Total number of trees constructed by the model.
     niterations: usize = 100,                       parse_fromstr;
 
+    /// Continue training the previously trained model instead of starting from scratch: another
+    /// `niterations` trees are added, boosting from the model's predictions on the current data.
+    warm_start: bool = false,                       parse_fromstr;
+
+    /// Write a checkpoint of the training state to `checkpoint_path` every `checkpoint_frequency`
+    /// iterations (0 disables checkpointing).
+    checkpoint_frequency: usize = 0,                parse_fromstr;
+
+    /// Path of the checkpoint file.
+    checkpoint_path: String = String::new(),        parse_fromstr;
+
+    /// Resume training from the checkpoint at this path. Training continues until a total of
+    /// `niterations` iterations, with results identical to an uninterrupted run.
+    resume_from: String = String::new(),            parse_fromstr;
+
     /// Learning rate / shrinkage: each tree's predictions is multiplied by this value.
     learning_rate: NumT = 1.0,                      parse_fromstr;
 
@@ -164,9 +233,48 @@ parse_config!(Config,
     /// Number of bits used to discretize the gradients (1, 2, 4, 8).
     discr_nbits: usize = 4,                         parse_fromstr;
 
+    /// Iterations after which the number of discretization bits doubles, e.g. `discr_nbits=2`
+    /// and `discr_nbits_schedule=50,150` uses 2 bits for the first 50 trees, 4 bits up to tree
+    /// 150, and 8 bits for the remaining trees.
+    discr_nbits_schedule: Vec<usize> = vec![],      parse_vec;
+
+    /// Double the number of discretization bits for the remaining trees when the quantization
+    /// error of the gradients of a tree exceeds this threshold (disabled when 0). The error is the
+    /// mean absolute difference between the discretized and the clipped gradients, relative to
+    /// the mean absolute clipped gradient.
+    discr_nbits_error_threshold: NumT = 0.0,        parse_fromstr;
+
+    /// Sum the exact floating point gradients instead of the discretized ones when building the
+    /// histograms. This is much slower, and is meant to measure how much accuracy the
+    /// discretization costs.
+    exact_gradients: bool = false,                  parse_fromstr;
+
+    /// Discretize the gradients with unbiased stochastic rounding instead of rounding to the
+    /// nearest level, so that the expected discretized gradient equals the clipped gradient.
+    /// Mostly useful with a low `discr_nbits`. Seeded from `random_seed`.
+    stochastic_rounding: bool = false,              parse_fromstr;
+
+    /// How the bounds of the discretized gradients are chosen; gradients outside the bounds are
+    /// clipped. `objective` uses the objective's own bounds. The other strategies recompute the
+    /// bounds from the gradients in each iteration: `symmetric` uses plus and minus the smallest
+    /// absolute extreme gradient, `asymmetric` the minimum and maximum gradient, and `percentile`
+    /// the `gradient_bounds_quantile` and `1 - gradient_bounds_quantile` quantiles.
+    gradient_bounds: String = String::from("objective"), parse_fromstr;
+
+    /// Fraction of the gradients clipped at each side by `gradient_bounds=percentile`.
+    gradient_bounds_quantile: NumT = 0.01,          parse_fromstr;
+
     /// Maximum depth of trees.
     max_tree_depth: usize = 6,                      parse_fromstr;
 
+    /// Maximum number of leaves of trees (0 for no limit other than `max_tree_depth`).
+    max_leaves: usize = 0,                          parse_fromstr;
+
+    /// How trees are grown: depthwise splits nodes depth-first, leafwise always splits the leaf
+    /// with the highest gain (best-first). Use leafwise with `max_leaves` for deep but narrow
+    /// trees.
+    growth_policy: String = String::from("depthwise"), parse_fromstr;
+
     /// Ratio of (number of zero instance set 32-bit blocks) / (total number of instance set 32-bit
     /// blocks) is compared to `compression_threshold`. If this ratio exceeds
     /// `compression_threshold`, then compression is applied.
@@ -179,17 +287,68 @@ parse_config!(Config,
     /// Random number generation seed (e.g. for bagging, feature sampling).
     random_seed: u64 = 1,                           parse_fromstr;
 
+    /// Number of threads used to train. The default value 0 uses the number of logical CPUs, or
+    /// the value of the `RAYON_NUM_THREADS` environment variable if set. The trained model does
+    /// not depend on the number of threads.
+    nthreads: usize = 0,                            parse_fromstr;
+
     /// Fraction of features used by each tree.
     feature_fraction: NumT = 1.0,                   parse_fromstr;
 
+    /// Fraction of the features of a tree that is used at each depth of the tree.
+    feature_fraction_bylevel: NumT = 1.0,           parse_fromstr;
+
+    /// Fraction of the features of a tree level that is used to split each node. Only the
+    /// histograms of these features are constructed.
+    feature_fraction_bynode: NumT = 1.0,            parse_fromstr;
+
     /// Example fraction / bagging fraction. Fraction of examples used by each tree.
     example_fraction: NumT = 1.0,                   parse_fromstr;
 
+    /// Sample the examples with replacement (bootstrap). This also enables example sampling when
+    /// `example_fraction` is 1.0.
+    example_replacement: bool = false,              parse_fromstr;
+
+    /// Example sampling strategy (uniform, stratified, goss). Uniform sampling uses
+    /// `example_fraction` of the examples. Stratified sampling is for binary targets: it samples
+    /// the negative and positive examples separately, see `neg_example_fraction` and
+    /// `pos_example_fraction`. Gradient-based one-side sampling (goss) keeps the `top_rate`
+    /// fraction of examples with the largest absolute gradients, and samples an `other_rate`
+    /// fraction of all examples from the remaining ones. The gradients of the latter are weighted
+    /// by (1 - top_rate) / other_rate; the l1 and huber leaf values ignore the weights.
+    example_sampling: String = String::from("uniform"), parse_fromstr;
+
+    /// Stratified sampling: fraction of the negative examples in each bag. A negative value uses
+    /// `example_fraction`, which keeps the class ratio. Values above 1.0 over-sample the class and
+    /// require `example_replacement`.
+    neg_example_fraction: NumT = -1.0,              parse_fromstr;
+
+    /// Stratified sampling: fraction of the positive examples in each bag, see
+    /// `neg_example_fraction`.
+    pos_example_fraction: NumT = -1.0,              parse_fromstr;
+
+    /// GOSS: fraction of examples with the largest absolute gradients.
+    top_rate: NumT = 0.2,                           parse_fromstr;
+
+    /// GOSS: fraction of examples sampled from the examples with smaller gradients.
+    other_rate: NumT = 0.1,                         parse_fromstr;
+
     /// Frequency of re-sampling features/examples; features and examples are sampled every
     /// `sample_freq` iterations. Feature pre-processing also runs at the same moments.
     sample_freq: usize = 1,                         parse_fromstr;
 );
 
+impl Config {
+    /// The maximum log level corresponding to `verbosity`.
+    pub fn log_level_filter(&self) -> LevelFilter {
+        match self.verbosity {
+            0 => LevelFilter::Warn,
+            1 => LevelFilter::Info,
+            _ => LevelFilter::Debug,
+        }
+    }
+}
+
 
 
 
@@ -243,7 +402,7 @@ macro_rules! gen_python_repr {
     }
 }
 
-gen_python_repr!(owned, [NumT, usize, u64, u32, u8]);
+gen_python_repr!(owned, [NumT, usize, isize, u64, u32, u8]);
 
 
 
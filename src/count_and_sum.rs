@@ -8,11 +8,15 @@
 use std::arch::x86_64::*;
 
 use crate::bitblock::{BitBlock, BitBlocks};
+use crate::simd::scalar;
+#[cfg(target_arch = "x86_64")]
+use crate::simd::has_avx2;
 use crate::bitslice::{BitsliceLayout, BitsliceWithLayout};
 use crate::bitslice::{BitsliceLayout1, BitsliceLayout2, BitsliceLayout4, BitsliceLayout8};
 
 
 /// Carry-safe adder.
+#[cfg(target_arch = "x86_64")]
 macro_rules! csa {
     ( $h:ident, $l:ident; $a:ident, $b:ident, $c:ident ) => {{
         let u = _mm256_xor_si256($a, $b);
@@ -32,6 +36,7 @@ macro_rules! csa {
 // ------------------------------------------------------------------------------------------------
 
 /// Internals that depend on the width of the individual units.
+#[cfg(target_arch = "x86_64")]
 unsafe trait HarleySealInternals {
     type Weights;
 
@@ -48,8 +53,11 @@ unsafe trait HarleySealInternals {
     unsafe fn reduce_total(total: __m256i, ws: Self::Weights) -> u64;
 }
 
+#[cfg(target_arch = "x86_64")]
 type HarleySealWeights32 = (u8, u8, u8, u8, u8, u8, u8, u8);
+#[cfg(target_arch = "x86_64")]
 struct Internals32;
+#[cfg(target_arch = "x86_64")]
 unsafe impl HarleySealInternals for Internals32 {
     type Weights = HarleySealWeights32;
 
@@ -75,7 +83,7 @@ unsafe impl HarleySealInternals for Internals32 {
 
     #[inline(always)]
     unsafe fn vshiftl(a: __m256i, imm8: i32) -> __m256i {
-        _mm256_slli_epi32(a, imm8)
+        _mm256_sll_epi32(a, _mm_cvtsi32_si128(imm8))
     }
 
     #[inline(always)]
@@ -98,8 +106,11 @@ unsafe impl HarleySealInternals for Internals32 {
     }
 }
 
+#[cfg(target_arch = "x86_64")]
 type HarleySealWeights64 = (u8, u8, u8, u8);
+#[cfg(target_arch = "x86_64")]
 struct Internals64;
+#[cfg(target_arch = "x86_64")]
 unsafe impl HarleySealInternals for Internals64 {
     type Weights = HarleySealWeights64;
 
@@ -121,7 +132,7 @@ unsafe impl HarleySealInternals for Internals64 {
 
     #[inline(always)]
     unsafe fn vshiftl(a: __m256i, imm8: i32) -> __m256i {
-        _mm256_slli_epi64(a, imm8)
+        _mm256_sll_epi64(a, _mm_cvtsi32_si128(imm8))
     }
 
     #[inline(always)]
@@ -138,6 +149,8 @@ unsafe impl HarleySealInternals for Internals64 {
     }
 }
 
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,popcnt")]
 unsafe fn harley_seal<Input, LoadFn, Internals>(d: &Input, n: usize, load: LoadFn,
         weights: <Internals as HarleySealInternals>::Weights)
     -> u64
@@ -194,15 +207,37 @@ where LoadFn: Fn(&Input, usize) -> __m256i,
     Internals::reduce_total(total, weights)
 }
 
+/// Maximum number of blocks summed by a single `harley_seal` call with 32-bit lane counters: each
+/// lane counts at most 32 bits per block, so this keeps the lane counters from overflowing.
+#[cfg(target_arch = "x86_64")]
+const HARLEY_SEAL32_MAX_BLOCKS: usize = 1 << 26;
+
+#[cfg(target_arch = "x86_64")]
 fn harley_seal32<Input, LoadFn>(d: &Input, n: usize, load: LoadFn, weights: HarleySealWeights32)
     -> u64
 where LoadFn: Fn(&Input, usize) -> __m256i
 {
-    unsafe {
-        harley_seal::<Input, LoadFn, Internals32>(d, n, load, weights)
+    harley_seal32_chunked(d, n, load, weights, HARLEY_SEAL32_MAX_BLOCKS)
+}
+
+/// `harley_seal32` in chunks of at most `max_blocks` blocks.
+#[cfg(target_arch = "x86_64")]
+fn harley_seal32_chunked<Input, LoadFn>(d: &Input, n: usize, load: LoadFn,
+                                        weights: HarleySealWeights32, max_blocks: usize) -> u64
+where LoadFn: Fn(&Input, usize) -> __m256i
+{
+    let mut sum = 0;
+    let mut start = 0;
+    while start < n {
+        let m = usize::min(n - start, max_blocks);
+        let chunk_load = |d: &Input, i: usize| load(d, start + i);
+        sum += unsafe { harley_seal::<Input, _, Internals32>(d, m, chunk_load, weights) };
+        start += m;
     }
+    sum
 }
 
+#[cfg(target_arch = "x86_64")]
 fn harley_seal64<Input, LoadFn>(d: &Input, n: usize, load: LoadFn, weights: HarleySealWeights64)
     -> u64
 where LoadFn: Fn(&Input, usize) -> __m256i
@@ -219,6 +254,7 @@ where LoadFn: Fn(&Input, usize) -> __m256i
 
 
 
+#[cfg(target_arch = "x86_64")]
 #[allow(unused_macros)]
 macro_rules! loadfn {
     ($name:ident < Uncompressed > () ) => {
@@ -367,22 +403,36 @@ macro_rules! loadfn {
 }
 
 
+#[cfg(target_arch = "x86_64")]
 loadfn!(load_and2_c00<Uncompressed>());
+#[cfg(target_arch = "x86_64")]
 loadfn!(load_and2_c10<Compressed10>());
 
+#[cfg(target_arch = "x86_64")]
 loadfn!(load_and3_w1_c00<Uncompressed>(__m256i));
+#[cfg(target_arch = "x86_64")]
 loadfn!(load_and3_w2_c00<Uncompressed>(__m128i));
+#[cfg(target_arch = "x86_64")]
 loadfn!(load_and3_w4_c00<Uncompressed>(u64));
+#[cfg(target_arch = "x86_64")]
 loadfn!(load_and3_w8_c00<Uncompressed>(u32));
 
+#[cfg(target_arch = "x86_64")]
 loadfn!(load_and3_w1_c10<Compressed10>(__m256i));
+#[cfg(target_arch = "x86_64")]
 loadfn!(load_and3_w2_c10<Compressed10>(__m128i));
+#[cfg(target_arch = "x86_64")]
 loadfn!(load_and3_w4_c10<Compressed10>(u64));
+#[cfg(target_arch = "x86_64")]
 loadfn!(load_and3_w8_c10<Compressed10>(u32));
 
+#[cfg(target_arch = "x86_64")]
 loadfn!(load_and3_w1_c01<Compressed01>(__m256i));
+#[cfg(target_arch = "x86_64")]
 loadfn!(load_and3_w2_c01<Compressed01>(__m128i));
+#[cfg(target_arch = "x86_64")]
 loadfn!(load_and3_w4_c01<Compressed01>(u64));
+#[cfg(target_arch = "x86_64")]
 loadfn!(load_and3_w8_c01<Compressed01>(u32));
 
 
@@ -392,83 +442,149 @@ loadfn!(load_and3_w8_c01<Compressed01>(u32));
 
 // - Harley-Seal based counting and summing: uncompressed, compressed10,and compressed01 ----------
 
-pub fn count_and2_c00(blocks1: &BitBlocks, blocks2: &BitBlocks) -> u64 {
-    let ptr1 = blocks1.as_ptr() as *const __m256i;
-    let ptr2 = blocks2.as_ptr() as *const __m256i;
-    harley_seal64(&(ptr1, ptr2), blocks1.len(), load_and2_c00, (1, 1, 1, 1))
+pub fn count_and2_c00(blocks1: &[BitBlock], blocks2: &[BitBlock]) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2() {
+            let ptr1 = blocks1.as_ptr() as *const __m256i;
+            let ptr2 = blocks2.as_ptr() as *const __m256i;
+            return harley_seal64(&(ptr1, ptr2), blocks1.len(), load_and2_c00, (1, 1, 1, 1));
+        }
+    }
+    unsafe { scalar::bitvec_count_and_uc(blocks1, blocks2) }
 }
 
-pub fn count_and2_c10(indexes1: &BitBlocks, blocks1: &BitBlocks, blocks2: &BitBlocks) -> u64 {
-    let idxs = indexes1.as_ptr() as *const __m256i;
-    let ptr1 = blocks1.as_ptr() as *const __m256i;
-    let ptr2 = blocks2.as_ptr() as *const __m256i;
-    harley_seal64(&(idxs, ptr1, ptr2), blocks1.len(), load_and2_c10, (1, 1, 1, 1))
+pub fn count_and2_c10(indexes1: &[BitBlock], blocks1: &[BitBlock], blocks2: &[BitBlock]) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2() {
+            let idxs = indexes1.as_ptr() as *const __m256i;
+            let ptr1 = blocks1.as_ptr() as *const __m256i;
+            let ptr2 = blocks2.as_ptr() as *const __m256i;
+            return harley_seal64(&(idxs, ptr1, ptr2), blocks1.len(), load_and2_c10, (1, 1, 1, 1));
+        }
+    }
+    unsafe { scalar::bitvec_count_and_c(blocks1, indexes1, blocks2) }
 }
 
-pub fn sum_and3_w1_c00(bitslice: &BitBlocks, bset1: &BitBlocks, bset2: &BitBlocks) -> u64 {
-    let slice = bitslice.as_ptr() as *const __m256i;
-    let ptr1 = bset1.as_ptr() as *const __m256i;
-    let ptr2 = bset2.as_ptr() as *const __m256i;
-    harley_seal64(&(slice, ptr1, ptr2), bitslice.len(), load_and3_w1_c00, (1, 1, 1, 1))
+pub fn sum_and3_w1_c00(bitslice: &[BitBlock], bset1: &[BitBlock], bset2: &[BitBlock]) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2() {
+            let slice = bitslice.as_ptr() as *const __m256i;
+            let ptr1 = bset1.as_ptr() as *const __m256i;
+            let ptr2 = bset2.as_ptr() as *const __m256i;
+            return harley_seal64(&(slice, ptr1, ptr2), bitslice.len(), load_and3_w1_c00,
+                                 (1, 1, 1, 1));
+        }
+    }
+    unsafe { scalar::btslce_summ1_uc(bitslice, bset1, bset2) }
 }
 
-pub fn sum_and3_w2_c00(bitslice: &BitBlocks, bset1: &BitBlocks, bset2: &BitBlocks) -> u64 {
-    let slice = bitslice.as_ptr() as *const __m256i;
-    let ptr1 = bset1.as_ptr() as *const __m256i;
-    let ptr2 = bset2.as_ptr() as *const __m256i;
-    harley_seal64(&(slice, ptr1, ptr2), bitslice.len(), load_and3_w2_c00, (1, 1, 2, 2))
+pub fn sum_and3_w2_c00(bitslice: &[BitBlock], bset1: &[BitBlock], bset2: &[BitBlock]) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2() {
+            let slice = bitslice.as_ptr() as *const __m256i;
+            let ptr1 = bset1.as_ptr() as *const __m256i;
+            let ptr2 = bset2.as_ptr() as *const __m256i;
+            return harley_seal64(&(slice, ptr1, ptr2), bitslice.len(), load_and3_w2_c00,
+                                 (1, 1, 2, 2));
+        }
+    }
+    unsafe { scalar::btslce_summ2_uc(bitslice, bset1, bset2) }
 }
 
-pub fn sum_and3_w4_c00(bitslice: &BitBlocks, bset1: &BitBlocks, bset2: &BitBlocks) -> u64 {
-    let slice = bitslice.as_ptr() as *const __m256i;
-    let ptr1 = bset1.as_ptr() as *const __m256i;
-    let ptr2 = bset2.as_ptr() as *const __m256i;
-    harley_seal64(&(slice, ptr1, ptr2), bitslice.len(), load_and3_w4_c00, (1, 2, 4, 8))
+pub fn sum_and3_w4_c00(bitslice: &[BitBlock], bset1: &[BitBlock], bset2: &[BitBlock]) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2() {
+            let slice = bitslice.as_ptr() as *const __m256i;
+            let ptr1 = bset1.as_ptr() as *const __m256i;
+            let ptr2 = bset2.as_ptr() as *const __m256i;
+            return harley_seal64(&(slice, ptr1, ptr2), bitslice.len(), load_and3_w4_c00,
+                                 (1, 2, 4, 8));
+        }
+    }
+    unsafe { scalar::btslce_summ4_uc(bitslice, bset1, bset2) }
 }
 
-pub fn sum_and3_w8_c00(bitslice: &BitBlocks, bset1: &BitBlocks, bset2: &BitBlocks) -> u64 {
-    let slice = bitslice.as_ptr() as *const __m256i;
-    let ptr1 = bset1.as_ptr() as *const __m256i;
-    let ptr2 = bset2.as_ptr() as *const __m256i;
-    harley_seal32(&(slice, ptr1, ptr2), bitslice.len(), load_and3_w8_c00,
-                  (1, 2, 4, 8, 16, 32, 64, 128))
+pub fn sum_and3_w8_c00(bitslice: &[BitBlock], bset1: &[BitBlock], bset2: &[BitBlock]) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2() {
+            let slice = bitslice.as_ptr() as *const __m256i;
+            let ptr1 = bset1.as_ptr() as *const __m256i;
+            let ptr2 = bset2.as_ptr() as *const __m256i;
+            return harley_seal32(&(slice, ptr1, ptr2), bitslice.len(), load_and3_w8_c00,
+                                 (1, 2, 4, 8, 16, 32, 64, 128));
+        }
+    }
+    unsafe { scalar::btslce_summ8_uc(bitslice, bset1, bset2) }
 }
 
-pub fn sum_and3_w1_c10(bitslice: &BitBlocks, idxs1: &BitBlocks, bset1: &BitBlocks,
-                      bset2: &BitBlocks) -> u64 {
-    let slice = bitslice.as_ptr() as *const __m256i;
-    let idxs = idxs1.as_ptr() as *const __m256i;
-    let ptr1 = bset1.as_ptr() as *const __m256i;
-    let ptr2 = bset2.as_ptr() as *const __m256i;
-    harley_seal64(&(slice, idxs, ptr1, ptr2), bitslice.len(), load_and3_w1_c10, (1, 1, 1, 1))
+pub fn sum_and3_w1_c10(bitslice: &[BitBlock], idxs1: &[BitBlock], bset1: &[BitBlock],
+                      bset2: &[BitBlock]) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2() {
+            let slice = bitslice.as_ptr() as *const __m256i;
+            let idxs = idxs1.as_ptr() as *const __m256i;
+            let ptr1 = bset1.as_ptr() as *const __m256i;
+            let ptr2 = bset2.as_ptr() as *const __m256i;
+            return harley_seal64(&(slice, idxs, ptr1, ptr2), bitslice.len(), load_and3_w1_c10,
+                                 (1, 1, 1, 1));
+        }
+    }
+    unsafe { scalar::btslce_summ1_c(bitslice, idxs1, bset1, bset2) }
 }
 
-pub fn sum_and3_w2_c10(bitslice: &BitBlocks, idxs1: &BitBlocks, bset1: &BitBlocks,
-                      bset2: &BitBlocks) -> u64 {
-    let slice = bitslice.as_ptr() as *const __m256i;
-    let idxs = idxs1.as_ptr() as *const __m256i;
-    let ptr1 = bset1.as_ptr() as *const __m256i;
-    let ptr2 = bset2.as_ptr() as *const __m256i;
-    harley_seal64(&(slice, idxs, ptr1, ptr2), bitslice.len(), load_and3_w2_c10, (1, 1, 2, 2))
+pub fn sum_and3_w2_c10(bitslice: &[BitBlock], idxs1: &[BitBlock], bset1: &[BitBlock],
+                      bset2: &[BitBlock]) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2() {
+            let slice = bitslice.as_ptr() as *const __m256i;
+            let idxs = idxs1.as_ptr() as *const __m256i;
+            let ptr1 = bset1.as_ptr() as *const __m256i;
+            let ptr2 = bset2.as_ptr() as *const __m256i;
+            return harley_seal64(&(slice, idxs, ptr1, ptr2), bitslice.len(), load_and3_w2_c10,
+                                 (1, 1, 2, 2));
+        }
+    }
+    unsafe { scalar::btslce_summ2_c(bitslice, idxs1, bset1, bset2) }
 }
 
-pub fn sum_and3_w4_c10(bitslice: &BitBlocks, idxs1: &BitBlocks, bset1: &BitBlocks,
-                      bset2: &BitBlocks) -> u64 {
-    let slice = bitslice.as_ptr() as *const __m256i;
-    let idxs = idxs1.as_ptr() as *const __m256i;
-    let ptr1 = bset1.as_ptr() as *const __m256i;
-    let ptr2 = bset2.as_ptr() as *const __m256i;
-    harley_seal64(&(slice, idxs, ptr1, ptr2), bitslice.len(), load_and3_w4_c10, (1, 2, 4, 8))
+pub fn sum_and3_w4_c10(bitslice: &[BitBlock], idxs1: &[BitBlock], bset1: &[BitBlock],
+                      bset2: &[BitBlock]) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2() {
+            let slice = bitslice.as_ptr() as *const __m256i;
+            let idxs = idxs1.as_ptr() as *const __m256i;
+            let ptr1 = bset1.as_ptr() as *const __m256i;
+            let ptr2 = bset2.as_ptr() as *const __m256i;
+            return harley_seal64(&(slice, idxs, ptr1, ptr2), bitslice.len(), load_and3_w4_c10,
+                                 (1, 2, 4, 8));
+        }
+    }
+    unsafe { scalar::btslce_summ4_c(bitslice, idxs1, bset1, bset2) }
 }
 
-pub fn sum_and3_w8_c10(bitslice: &BitBlocks, idxs1: &BitBlocks, bset1: &BitBlocks,
-                      bset2: &BitBlocks) -> u64 {
-    let slice = bitslice.as_ptr() as *const __m256i;
-    let idxs = idxs1.as_ptr() as *const __m256i;
-    let ptr1 = bset1.as_ptr() as *const __m256i;
-    let ptr2 = bset2.as_ptr() as *const __m256i;
-    harley_seal32(&(slice, idxs, ptr1, ptr2), bitslice.len(), load_and3_w8_c10,
-                  (1, 2, 4, 8, 16, 32, 64, 128))
+pub fn sum_and3_w8_c10(bitslice: &[BitBlock], idxs1: &[BitBlock], bset1: &[BitBlock],
+                      bset2: &[BitBlock]) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2() {
+            let slice = bitslice.as_ptr() as *const __m256i;
+            let idxs = idxs1.as_ptr() as *const __m256i;
+            let ptr1 = bset1.as_ptr() as *const __m256i;
+            let ptr2 = bset2.as_ptr() as *const __m256i;
+            return harley_seal32(&(slice, idxs, ptr1, ptr2), bitslice.len(), load_and3_w8_c10,
+                                 (1, 2, 4, 8, 16, 32, 64, 128));
+        }
+    }
+    unsafe { scalar::btslce_summ8_c(bitslice, idxs1, bset1, bset2) }
 }
 
 
@@ -594,12 +710,15 @@ mod test {
             if b1 && b2 { sum += 1; }
         }
 
-        let bset1_ptr = bset1.as_ptr() as *const __m256i;
-        let bset2_ptr = bset2.as_ptr() as *const __m256i;
-        let sum_harley_seal1 = harley_seal64(&(bset1_ptr, bset2_ptr), bset1.len(),
-                                             load_and2_c00, (1, 1, 1, 1));
+        #[cfg(target_arch = "x86_64")]
+        {
+            let bset1_ptr = bset1.as_ptr() as *const __m256i;
+            let bset2_ptr = bset2.as_ptr() as *const __m256i;
+            let sum_harley_seal1 = harley_seal64(&(bset1_ptr, bset2_ptr), bset1.len(),
+                                                 load_and2_c00, (1, 1, 1, 1));
+            assert_eq!(sum, sum_harley_seal1);
+        }
         let sum_harley_seal2 = count_and2_c00(&bset1, &bset2);
-        assert_eq!(sum, sum_harley_seal1);
         assert_eq!(sum, sum_harley_seal2);
     }
 
@@ -621,13 +740,16 @@ mod test {
             if b1 && b2 { sum += 1; }
         }
 
-        let indexes_ptr = indexes.as_ptr() as *const __m256i;
-        let bset1_ptr = bset1.as_ptr() as *const __m256i;
-        let bset2_ptr = bset2.as_ptr() as *const __m256i;
-        let sum_harley_seal1 = harley_seal64(&(indexes_ptr, bset1_ptr, bset2_ptr),
-                                             bset1.len(), load_and2_c10, (1, 1, 1, 1));
+        #[cfg(target_arch = "x86_64")]
+        {
+            let indexes_ptr = indexes.as_ptr() as *const __m256i;
+            let bset1_ptr = bset1.as_ptr() as *const __m256i;
+            let bset2_ptr = bset2.as_ptr() as *const __m256i;
+            let sum_harley_seal1 = harley_seal64(&(indexes_ptr, bset1_ptr, bset2_ptr),
+                                                 bset1.len(), load_and2_c10, (1, 1, 1, 1));
+            assert_eq!(sum, sum_harley_seal1);
+        }
         let sum_harley_seal2 = count_and2_c10(&indexes, &bset1, &bset2);
-        assert_eq!(sum, sum_harley_seal1);
         assert_eq!(sum, sum_harley_seal2);
     }
 
@@ -653,13 +775,16 @@ mod test {
             }
         }
 
-        let indexes_ptr = indexes.as_ptr() as *const __m256i;
-        let bset1_ptr = bset1.as_ptr() as *const __m256i;
-        let bset2_ptr = bset2.as_ptr() as *const __m256i;
-        let sum_harley_seal1 = harley_seal64(&(indexes_ptr, bset1_ptr, bset2_ptr),
-                                             bset1.len(), load_and2_c10, (1, 1, 1, 1));
+        #[cfg(target_arch = "x86_64")]
+        {
+            let indexes_ptr = indexes.as_ptr() as *const __m256i;
+            let bset1_ptr = bset1.as_ptr() as *const __m256i;
+            let bset2_ptr = bset2.as_ptr() as *const __m256i;
+            let sum_harley_seal1 = harley_seal64(&(indexes_ptr, bset1_ptr, bset2_ptr),
+                                                 bset1.len(), load_and2_c10, (1, 1, 1, 1));
+            assert_eq!(sum, sum_harley_seal1);
+        }
         let sum_harley_seal2 = count_and2_c10(&indexes, &bset1, &bset2);
-        assert_eq!(sum, sum_harley_seal1);
         assert_eq!(sum, sum_harley_seal2);
     }
 
@@ -685,6 +810,7 @@ mod test {
         let mut view = bitslice.with_layout_mut::<L>();
         view.resize(n);
 
+        #[cfg(target_arch = "x86_64")]
         let nblocks = view.nblocks();
 
         let mut bset1 = Bitset::zeros(n);
@@ -704,22 +830,26 @@ mod test {
             }
         }
 
-        let bitslice_ptr = bitslice.as_bitblocks().as_ptr() as *const __m256i;
-        let bset1_ptr = bset1.as_ptr() as *const __m256i;
-        let bset2_ptr = bset2.as_ptr() as *const __m256i;
-
-        let w1 = (1, 1, 1, 1);
-        let w2 = (1, 1, 2, 2);
-        let w4 = (1, 2, 4, 8);
-        let w8 = (1, 2, 4, 8, 16, 32, 64, 128);
-
-        let sum_harley_seal1 = match L::width() {
-            1 => { harley_seal64(&(bitslice_ptr, bset1_ptr, bset2_ptr), nblocks, load_and3_w1_c00, w1) }
-            2 => { harley_seal64(&(bitslice_ptr, bset1_ptr, bset2_ptr), nblocks, load_and3_w2_c00, w2) }
-            4 => { harley_seal64(&(bitslice_ptr, bset1_ptr, bset2_ptr), nblocks, load_and3_w4_c00, w4) }
-            8 => { harley_seal32(&(bitslice_ptr, bset1_ptr, bset2_ptr), nblocks, load_and3_w8_c00, w8) }
-            _ => { panic!() }
-        };
+        #[cfg(target_arch = "x86_64")]
+        {
+            let bitslice_ptr = bitslice.as_bitblocks().as_ptr() as *const __m256i;
+            let bset1_ptr = bset1.as_ptr() as *const __m256i;
+            let bset2_ptr = bset2.as_ptr() as *const __m256i;
+
+            let w1 = (1, 1, 1, 1);
+            let w2 = (1, 1, 2, 2);
+            let w4 = (1, 2, 4, 8);
+            let w8 = (1, 2, 4, 8, 16, 32, 64, 128);
+
+            let sum_harley_seal1 = match L::width() {
+                1 => { harley_seal64(&(bitslice_ptr, bset1_ptr, bset2_ptr), nblocks, load_and3_w1_c00, w1) }
+                2 => { harley_seal64(&(bitslice_ptr, bset1_ptr, bset2_ptr), nblocks, load_and3_w2_c00, w2) }
+                4 => { harley_seal64(&(bitslice_ptr, bset1_ptr, bset2_ptr), nblocks, load_and3_w4_c00, w4) }
+                8 => { harley_seal32(&(bitslice_ptr, bset1_ptr, bset2_ptr), nblocks, load_and3_w8_c00, w8) }
+                _ => { panic!() }
+            };
+            assert_eq!(sum, sum_harley_seal1);
+        }
 
         let sum_harley_seal2 = match L::width() {
             1 => { sum_and3_w1_c00(bitslice.as_bitblocks(), &bset1, &bset2) }
@@ -729,10 +859,37 @@ mod test {
             _ => { panic!() }
         };
 
-        assert_eq!(sum, sum_harley_seal1);
         assert_eq!(sum, sum_harley_seal2);
     }
 
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn test_harley_seal32_chunked() {
+        let n = 20_000;
+        let mut bitslice = Bitslice::new();
+        let mut view = bitslice.with_layout_mut::<BitsliceLayout8>();
+        view.resize(n);
+        let nblocks = view.nblocks();
+        let bset = Bitset::ones(n);
+        let mut sum = 0;
+        for i in 0..n {
+            let k = ((7919 * i) % 256) as u8;
+            view.set_value(i, k);
+            sum += k as u64;
+        }
+
+        let bitslice_ptr = bitslice.as_bitblocks().as_ptr() as *const __m256i;
+        let bset_ptr = bset.as_ptr() as *const __m256i;
+        let w8 = (1, 2, 4, 8, 16, 32, 64, 128);
+
+        // chunks smaller than, equal to and not a multiple of the 16 blocks of the main loop
+        for &max_blocks in &[1, 7, 16, 100, nblocks] {
+            let chunked = harley_seal32_chunked(&(bitslice_ptr, bset_ptr, bset_ptr), nblocks,
+                                                load_and3_w8_c00, w8, max_blocks);
+            assert_eq!(sum, chunked, "max_blocks {}", max_blocks);
+        }
+    }
+
     #[test]
     fn test_sum_and3_c10() {
         sum_c10::<BitsliceLayout1>(20);
@@ -752,6 +909,7 @@ mod test {
         let mut view = bitslice.with_layout_mut::<L>();
         view.resize(m1 * 32);
 
+        #[cfg(target_arch = "x86_64")]
         let nblocks = view.nblocks();
 
         let mut bset1 = Bitset::zeros(m1 * 32);
@@ -777,23 +935,27 @@ mod test {
             }
         }
 
-        let bitslice_ptr = bitslice.as_bitblocks().as_ptr() as *const __m256i;
-        let indexes1_ptr = indexes.as_ptr() as *const __m256i;
-        let bset1_ptr = bset1.as_ptr() as *const __m256i;
-        let bset2_ptr = bset2.as_ptr() as *const __m256i;
-
-        let w1 = (1, 1, 1, 1);
-        let w2 = (1, 1, 2, 2);
-        let w4 = (1, 2, 4, 8);
-        let w8 = (1, 2, 4, 8, 16, 32, 64, 128);
-
-        let sum_harley_seal1 = match L::width() {
-            1 => { harley_seal64(&(bitslice_ptr, indexes1_ptr, bset1_ptr, bset2_ptr), nblocks, load_and3_w1_c10, w1) }
-            2 => { harley_seal64(&(bitslice_ptr, indexes1_ptr, bset1_ptr, bset2_ptr), nblocks, load_and3_w2_c10, w2) }
-            4 => { harley_seal64(&(bitslice_ptr, indexes1_ptr, bset1_ptr, bset2_ptr), nblocks, load_and3_w4_c10, w4) }
-            8 => { harley_seal32(&(bitslice_ptr, indexes1_ptr, bset1_ptr, bset2_ptr), nblocks, load_and3_w8_c10, w8) }
-            _ => { panic!() }
-        };
+        #[cfg(target_arch = "x86_64")]
+        {
+            let bitslice_ptr = bitslice.as_bitblocks().as_ptr() as *const __m256i;
+            let indexes1_ptr = indexes.as_ptr() as *const __m256i;
+            let bset1_ptr = bset1.as_ptr() as *const __m256i;
+            let bset2_ptr = bset2.as_ptr() as *const __m256i;
+
+            let w1 = (1, 1, 1, 1);
+            let w2 = (1, 1, 2, 2);
+            let w4 = (1, 2, 4, 8);
+            let w8 = (1, 2, 4, 8, 16, 32, 64, 128);
+
+            let sum_harley_seal1 = match L::width() {
+                1 => { harley_seal64(&(bitslice_ptr, indexes1_ptr, bset1_ptr, bset2_ptr), nblocks, load_and3_w1_c10, w1) }
+                2 => { harley_seal64(&(bitslice_ptr, indexes1_ptr, bset1_ptr, bset2_ptr), nblocks, load_and3_w2_c10, w2) }
+                4 => { harley_seal64(&(bitslice_ptr, indexes1_ptr, bset1_ptr, bset2_ptr), nblocks, load_and3_w4_c10, w4) }
+                8 => { harley_seal32(&(bitslice_ptr, indexes1_ptr, bset1_ptr, bset2_ptr), nblocks, load_and3_w8_c10, w8) }
+                _ => { panic!() }
+            };
+            assert_eq!(sum, sum_harley_seal1);
+        }
 
         let sum_harley_seal2 = match L::width() {
             1 => { sum_and3_w1_c10(bitslice.as_bitblocks(), &indexes, &bset1, &bset2) }
@@ -803,7 +965,6 @@ mod test {
             _ => { panic!() }
         };
 
-        assert_eq!(sum, sum_harley_seal1);
         assert_eq!(sum, sum_harley_seal2);
     }
 
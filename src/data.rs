@@ -9,6 +9,7 @@ use std::path::Path;
 use std::fs::File;
 
 use csv;
+use log::info;
 
 use crate::{NumT, POS_INF, NEG_INF, into_cat};
 use crate::config::Config;
@@ -30,6 +31,7 @@ pub struct Data {
     limits: Vec<(NumT, NumT)>, // feature min / max value
     ftypes: Vec<FeatType>,
     cards: Vec<usize>, // only for categorical
+    init_scores: Option<Vec<NumT>>, // initial prediction (base margin) of each example
 }
 
 impl Data {
@@ -110,8 +112,23 @@ impl Data {
                 .for_each(|(i, name)| names[i].push_str(name));
         }
 
+        // take out the initial score column, it is not a feature
+        let mut init_scores = None;
+        if config.init_score_column >= 0 {
+            let c = config.init_score_column as usize;
+            if c + 1 >= record_len {
+                return Err(format!("Invalid init_score_column {}", c));
+            }
+            init_scores = Some(features.remove(c));
+            limits.remove(c);
+            ftypes.remove(c);
+            cards.remove(c);
+            names.remove(c);
+            record_len -= 1;
+        }
+
         let target_id = record_len - 1;
-        println!("[   ] using target {} (column {})", names[target_id], target_id);
+        info!("[   ] using target {} (column {})", names[target_id], target_id);
 
         Ok(Data {
             max_nbins: config.max_nbins,
@@ -122,6 +139,7 @@ impl Data {
             limits,
             ftypes,
             cards,
+            init_scores,
         })
     }
 
@@ -143,6 +161,7 @@ impl Data {
             limits,
             ftypes,
             cards,
+            init_scores: None,
         }
     }
 
@@ -174,6 +193,14 @@ impl Data {
         Ok(())
     }
 
+    /// Set the initial score (base margin) of each example. Training starts from these
+    /// predictions, and they are added to the predictions of a model.
+    pub fn set_init_scores(&mut self, init_scores: &[NumT]) {
+        assert_eq!(init_scores.len(), self.nexamples);
+        self.init_scores = Some(init_scores.to_vec());
+    }
+
+    pub fn init_scores(&self) -> Option<&[NumT]> { self.init_scores.as_ref().map(|x| &x[..]) }
     pub fn nfeatures(&self) -> usize { self.nfeatures }
     pub fn nexamples(&self) -> usize { self.nexamples }
     pub fn feat_name(&self, feature: usize) -> &str { &self.names[feature] } // TODO rename
@@ -276,5 +303,23 @@ mod test {
         assert_eq!(data.feat_limits(0), (1.0, 4.0));
         assert_eq!(data.feat_limits(1), (2.0, 5.0));
         assert_eq!(data.feat_limits(2), (3.0, 6.0));
+        assert!(data.init_scores().is_none());
+    }
+
+    #[test]
+    fn init_score_column() {
+        let mut config = Config::new();
+        config.csv_has_header = true;
+        config.categorical_features = vec![2];
+        config.init_score_column = 1;
+
+        let data = Data::from_csv(&config, "a,s,c,t\n1.0,0.5,0,0\n4,-0.5,1,1\n").unwrap();
+
+        assert_eq!(data.nfeatures(), 2);
+        assert_eq!(data.get_feature(1)[1], 1.0);
+        assert_eq!(data.feat_name(1), "c");
+        assert_eq!(data.feat_card(1), 2);
+        assert_eq!(data.get_target(), &[0.0, 1.0]);
+        assert_eq!(data.init_scores(), Some(&[0.5, -0.5][..]));
     }
 }
@@ -5,10 +5,14 @@
 */
 
 use std::ops::Range;
+use std::cmp::Ordering;
 use std::slice::Iter;
 use std::iter::Cloned;
+use std::io::{Read, Write, Result as IoResult};
 
-use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+use rand::{Rng, RngCore, SeedableRng, Error as RandError};
 use rand::rngs::SmallRng;
 
 use crate::{NumT, CatT, into_cat, EPSILON};
@@ -16,54 +20,65 @@ use crate::config::Config;
 use crate::data::{Data, FeatType};
 use crate::slice_store::{SliceRange, BitBlockStore, BitVecRef};
 use crate::binner::Binner;
+use crate::bitblock::BitBlock;
 use crate::simd;
+use crate::checkpoint::{CheckpointWriter, CheckpointReader};
 
 const QUANTILE_EST_NBINS: usize = 512;
 
 pub struct InnerDataset<'a> {
-    rng: SmallRng,
+    rng: CountingRng,
     data: &'a Data,
     useful_features: Vec<usize>, // == first `nactive_features` are 'active'
     nactive_features: usize,
     nactive_examples: usize,     // == nexamples in bag
-    store: BitBlockStore,
-    bitvecs: Vec<Vec<SliceRange>>,
-    supercats: Vec<Vec<CatT>>,
-    split_candidates: Vec<Vec<NumT>>,
-    used_nbins: Vec<usize>,
+    features: Vec<FeatureBitvecs>,
     has_non_locard_cat_features: bool,
 }
 
+/// The bitvecs of a single feature and the splits they represent. Each feature owns its memory,
+/// so that features can be updated in parallel.
+struct FeatureBitvecs {
+    store: BitBlockStore,
+    bitvecs: Vec<SliceRange>,
+    used_nbins: usize,           // the first `used_nbins` bitvecs are in use
+    supercats: Vec<CatT>,        // hicard-cat: the super-category of each category
+    split_candidates: Vec<NumT>, // numerical: the split value of each bitvec
+}
+
 impl <'a> InnerDataset<'a> {
     fn new(config: &Config, data: &'a Data) -> Self {
-        let rng = SmallRng::seed_from_u64(config.random_seed);
+        let rng = CountingRng::new(config.random_seed);
         let mut has_non_locard_cat_features = false;
         let mut useful_features = Vec::new();
-        let mut store = BitBlockStore::new(2048);
 
         let nfeatures = data.nfeatures();
         let nexamples = data.nexamples();
-        let nactive_examples = (nexamples as NumT * config.example_fraction).round() as usize;
-
-        let mut bitvecs      = vec![Vec::new(); nfeatures];
-        let split_candidates = vec![Vec::new(); nfeatures];
-        let used_nbins       = vec![0; nfeatures];
-        let supercats        = Vec::new();
+        let nactive_examples = match config.example_sampling.as_str() {
+            "goss" => (nexamples as NumT * (config.top_rate + config.other_rate)).round() as usize,
+            "stratified" => stratified_sizes(config, data).iter().sum(),
+            _ => (nexamples as NumT * config.example_fraction).round() as usize,
+        };
 
         // find useful features and allocate bitvecs
+        let mut features = Vec::with_capacity(nfeatures);
         for feat_id in 0..data.nfeatures() {
             let max_nbins = data.max_nbins(feat_id);
-            if max_nbins == 0 { continue; } // not a useful feature, only 1 value
-
             let feat_bounds = data.feat_limits(feat_id);
-            if feat_bounds.0 == feat_bounds.1 { continue; } // not a useful feature, only 1 value
-
-            has_non_locard_cat_features |= data.feat_type(feat_id) != FeatType::LoCardCat;
-
-            useful_features.push(feat_id);
-            bitvecs[feat_id] = (0..max_nbins)
-                .map(|_| store.alloc_zero_bits(nactive_examples))
-                .collect();
+            let useful = max_nbins > 0                  // not a useful feature, only 1 value
+                && feat_bounds.0 != feat_bounds.1;      // not a useful feature, only 1 value
+
+            // +1: each bitvec starts at a cache line, i.e., at an even block
+            let nblocks = BitBlock::blocks_required_for(nactive_examples) + 1;
+            let mut feature = FeatureBitvecs::new(if useful { max_nbins * nblocks } else { 1 });
+            if useful {
+                has_non_locard_cat_features |= data.feat_type(feat_id) != FeatType::LoCardCat;
+                useful_features.push(feat_id);
+                feature.bitvecs = (0..max_nbins)
+                    .map(|_| feature.store.alloc_zero_bits(nactive_examples))
+                    .collect();
+            }
+            features.push(feature);
         }
 
         let nuseful = useful_features.len();
@@ -75,11 +90,7 @@ impl <'a> InnerDataset<'a> {
             useful_features,
             nactive_features,
             nactive_examples,
-            store,
-            bitvecs,
-            supercats,
-            split_candidates,
-            used_nbins,
+            features,
             has_non_locard_cat_features,
         }
     }
@@ -98,32 +109,73 @@ impl <'a> InnerDataset<'a> {
         &self.useful_features[0..self.nactive_features]
     }
 
-    fn initialize_supercats(&mut self) {
-        self.supercats.resize(self.data.nfeatures(), Vec::new());
+    /// Update the bitvecs of the active features in parallel. The bitvecs of the low-cardinality
+    /// categorical features only depend on the examples, so they are only updated if
+    /// `update_locard_cat` is true.
+    fn update_features<I>(&mut self, config: &Config, example_iter: I, grad: &[NumT],
+                          grad_bounds: (NumT, NumT), update_locard_cat: bool)
+    where I: IntoIterator<Item = usize> + Copy + Send + Sync
+    {
+        let data = self.data;
+        let nactive_examples = self.nactive_examples;
+        let mut active = vec![false; data.nfeatures()];
+        self.active_features().iter().for_each(|&feat_id| active[feat_id] = true);
+
+        self.features.par_iter_mut()
+            .enumerate()
+            .filter(|&(feat_id, _)| active[feat_id])
+            .for_each(|(feat_id, feature)| {
+                match data.feat_type(feat_id) {
+                    FeatType::LoCardCat => if update_locard_cat {
+                        feature.update_locard_cat(data, feat_id, example_iter);
+                    },
+                    FeatType::HiCardCat => {
+                        feature.update_hicard_cat(config, data, nactive_examples, feat_id,
+                                                  example_iter, grad, grad_bounds);
+                    },
+                    FeatType::Numerical => {
+                        feature.update_num(config, data, feat_id, example_iter, grad);
+                    },
+                }
+            });
+    }
+}
+
+impl FeatureBitvecs {
+    fn new(nblocks: usize) -> Self {
+        FeatureBitvecs {
+            store: BitBlockStore::new(nblocks),
+            bitvecs: Vec::new(),
+            used_nbins: 0,
+            supercats: Vec::new(),
+            split_candidates: Vec::new(),
+        }
     }
 
-    fn update_locard_cat<I>(&mut self, _config: &Config, feat_id: usize, example_iter: I)
+    fn update_locard_cat<I>(&mut self, data: &Data, feat_id: usize, example_iter: I)
     where I: IntoIterator<Item = usize> + Copy
     {
-        let data = self.data.get_feature(feat_id);
-        let get_cat = |i| into_cat(data[i]) as usize;
-        self.used_nbins[feat_id] = self.data.max_nbins(feat_id);
-        debug_assert_eq!(self.used_nbins[feat_id], self.bitvecs[feat_id].len());
-        Self::zero_bitvecs(&mut self.store, &self.bitvecs[feat_id]);
-        Self::fill_bitvecs(&mut self.store, &self.bitvecs[feat_id], example_iter, get_cat);
+        let values = data.get_feature(feat_id);
+        let get_cat = |i| into_cat(values[i]) as usize;
+        self.used_nbins = data.max_nbins(feat_id);
+        debug_assert_eq!(self.used_nbins, self.bitvecs.len());
+        Self::zero_bitvecs(&mut self.store, &self.bitvecs);
+        Self::fill_bitvecs(&mut self.store, &self.bitvecs, example_iter, get_cat);
     }
 
-    fn update_hicard_cat<I>(&mut self, config: &Config, feat_id: usize, example_iter: I,
-                            grad: &[NumT], grad_bounds: (NumT, NumT))
+    #[allow(clippy::too_many_arguments)]
+    fn update_hicard_cat<I>(&mut self, config: &Config, data: &Data, nactive_examples: usize,
+                            feat_id: usize, example_iter: I, grad: &[NumT],
+                            grad_bounds: (NumT, NumT))
     where I: IntoIterator<Item = usize> + Copy
     {
-        let data = self.data.get_feature(feat_id);
-        let card = self.data.feat_card(feat_id);
+        let values = data.get_feature(feat_id);
+        let card = data.feat_card(feat_id);
 
         // collect gradient sums & counts per categorical value
-        let mut grad_stat_pairs: Vec<(NumT, u32)> = vec![(0.0, 0); card];
+        let mut grad_stat_pairs: Vec<(NumT, u64)> = vec![(0.0, 0); card];
         for (i, j) in example_iter.into_iter().enumerate() {
-            let category = into_cat(data[j]) as usize;
+            let category = into_cat(values[j]) as usize;
             let entry = &mut grad_stat_pairs[category];
             entry.0 += grad[i];
             entry.1 += 1;
@@ -131,9 +183,9 @@ impl <'a> InnerDataset<'a> {
 
         // accumulate category values (mean) -> this determines their ordening
         // combine similar categories using quantile estimation into 'super-categories'
-        let mut bins = vec![0u32; QUANTILE_EST_NBINS];
+        let mut bins = vec![0u64; QUANTILE_EST_NBINS];
         let mut binner = Binner::new(&mut bins, grad_bounds);
-        let combiner = |bin: &mut u32, count: u32| *bin += count;
+        let combiner = |bin: &mut u64, count: u64| *bin += count;
         for (sum, count) in grad_stat_pairs.iter_mut() {
             if *count == 0 { continue; }
             *sum = *sum / *count as NumT; // = category weight
@@ -141,9 +193,9 @@ impl <'a> InnerDataset<'a> {
         }
 
         // extract quantiles
-        let extractor = |bin: &u32| *bin;
-        let rank_step = self.nactive_examples as NumT / (config.max_nbins + 1) as NumT;
-        let ranks = (1..=config.max_nbins).map(|i| (i as NumT * rank_step).round() as u32 - 1);
+        let extractor = |bin: &u64| *bin;
+        let rank_step = nactive_examples as NumT / (config.max_nbins + 1) as NumT;
+        let ranks = (1..=config.max_nbins).map(|i| (i as NumT * rank_step).round() as u64 - 1);
         let qbins = binner.rank_iter(ranks, extractor);
         let mut last_bin = usize::max_value();
         let mut split_weights = Vec::with_capacity(config.max_nbins);
@@ -173,22 +225,22 @@ impl <'a> InnerDataset<'a> {
         }
 
         // generate bitvecs
-        let bitvecs = &self.bitvecs[feat_id][0..supercard];
-        let get_cat = |i| supercats[into_cat(data[i]) as usize] as usize;
+        let bitvecs = &self.bitvecs[0..supercard];
+        let get_cat = |i| supercats[into_cat(values[i]) as usize] as usize;
         Self::zero_bitvecs(&mut self.store, bitvecs);
         Self::fill_bitvecs(&mut self.store, bitvecs, example_iter, get_cat);
         Self::accumulate_bitvecs(&mut self.store, bitvecs);
 
-        self.used_nbins[feat_id] = supercard;
-        self.supercats[feat_id] = supercats;
+        self.used_nbins = supercard;
+        self.supercats = supercats;
     }
 
-    fn update_num<'b, I>(&mut self, config: &Config, feat_id: usize, example_iter: I,
-                         grad: &[NumT])
+    fn update_num<I>(&mut self, config: &Config, data: &Data, feat_id: usize, example_iter: I,
+                     grad: &[NumT])
     where I: IntoIterator<Item = usize> + Copy
     {
-        let data = self.data.get_feature(feat_id);
-        let feat_bounds = self.data.feat_limits(feat_id);
+        let values = data.get_feature(feat_id);
+        let feat_bounds = data.feat_limits(feat_id);
 
         // quantile est. weighted by grad. vals so there is a variation in the splits we generate
         let mut bins: Vec<NumT> = vec![0.0; QUANTILE_EST_NBINS];
@@ -196,7 +248,7 @@ impl <'a> InnerDataset<'a> {
         let combiner = |bin: &mut NumT, grad_value: NumT| *bin += grad_value;
         let mut grad_weight_sum = 0.0;
         for j in example_iter.into_iter() { // XXX apply transformation to grad weights?
-            let (feat_value, grad_value) = (data[j], grad[j].abs() + EPSILON);
+            let (feat_value, grad_value) = (values[j], grad[j].abs() + EPSILON);
             grad_weight_sum += grad_value;
             binner.insert(feat_value, grad_value, combiner);
         }
@@ -238,9 +290,9 @@ impl <'a> InnerDataset<'a> {
         }
 
         // construct bitvecs
-        let bitvecs = &self.bitvecs[feat_id][0..nsplit_candidates];
+        let bitvecs = &self.bitvecs[0..nsplit_candidates];
         let get_cat = |i| {
-            let feat_value = data[i];
+            let feat_value = values[i];
             //let cat = split_candidates.binary_search_by(|&split_cand| {
             //    if split_cand < feat_value { Ordering::Less }
             //    else                       { Ordering::Greater }
@@ -261,8 +313,8 @@ impl <'a> InnerDataset<'a> {
         Self::fill_bitvecs(&mut self.store, bitvecs, example_iter, get_cat);
         Self::accumulate_bitvecs(&mut self.store, bitvecs);
 
-        self.used_nbins[feat_id] = nsplit_candidates;
-        self.split_candidates[feat_id] = split_candidates;
+        self.used_nbins = nsplit_candidates;
+        self.split_candidates = split_candidates;
     }
 
     fn zero_bitvecs(store: &mut BitBlockStore, bitvecs: &[SliceRange]) {
@@ -308,23 +360,50 @@ pub struct Dataset<'a> {
     inner: InnerDataset<'a>,
     index_buffer: Vec<usize>, // 0..k examples are active examples, empty if ex.sampling disabled
     update_count: usize,
+    last_full_update: Option<FullUpdate>, // only if sample_freq > 1, for checkpoints
+    example_weights: Vec<NumT>, // GOSS weight of each example, empty if GOSS disabled
+    strata: Vec<Vec<usize>>, // negative and positive examples, empty if not stratified
+    goss_bound: NumT, // largest weighted absolute gradient of the sampled small-gradient examples
 }
 
 impl <'a> Dataset<'a> {
     pub fn new(config: &Config, data: &'a Data) -> Self {
+        let (goss, stratified) = match config.example_sampling.as_str() {
+            "uniform"    => (false, false),
+            "goss"       => (true, false),
+            "stratified" => (false, true),
+            _ => panic!("unknown example_sampling '{}'", config.example_sampling),
+        };
+        assert!(!goss || (config.other_rate > 0.0 && config.top_rate + config.other_rate <= 1.0),
+                "invalid GOSS top_rate / other_rate");
+
         let mut inner = InnerDataset::new(config, data);
         let mut index_buffer = Vec::new();
+        let mut strata = Vec::new();
+
+        if stratified {
+            let target = data.get_target();
+            assert!(target.iter().all(|&t| t == 0.0 || t == 1.0),
+                    "stratified sampling requires a binary target");
+            let sizes = stratified_sizes(config, data);
+            strata = vec![Vec::new(), Vec::new()];
+            for (i, &t) in target.iter().enumerate() { strata[t as usize].push(i); }
+            assert!(config.example_replacement
+                    || (sizes[0] <= strata[0].len() && sizes[1] <= strata[1].len()),
+                    "over-sampling a class requires example_replacement");
+        }
             
-        if config.example_fraction < 1.0 {
+        if config.example_fraction < 1.0 || config.example_replacement || goss || stratified {
             // bagging is enabled, choose examples
             index_buffer = (0..inner.data.nexamples()).collect();
+            let nactive = inner.nactive_examples;
+            if index_buffer.len() < nactive { index_buffer.resize(nactive, 0); } // over-sampling
         } else {
             // no bagging, update locard-cat once and reuse throughout boosting
             let all_examples = RangeIntoIter(inner.data.nexamples());
-            for u in 0..inner.useful_features.len() {
-                let feat_id = inner.useful_features[u];
+            for &feat_id in &inner.useful_features {
                 if inner.data.feat_type(feat_id) == FeatType::LoCardCat {
-                    inner.update_locard_cat(config, feat_id, &all_examples);
+                    inner.features[feat_id].update_locard_cat(inner.data, feat_id, &all_examples);
                 }
             }
         }
@@ -333,12 +412,71 @@ impl <'a> Dataset<'a> {
             inner,
             index_buffer,
             update_count: 0,
+            last_full_update: None,
+            example_weights: Vec::new(),
+            strata,
+            goss_bound: 0.0,
+        }
+    }
+
+    fn sample_examples(&mut self, config: &Config, grad: &[NumT], grad_bounds: (NumT, NumT)) {
+        let k = self.nactive_examples();
+        if config.example_sampling == "goss" {
+            self.sample_goss(config, grad, grad_bounds);
+        } else if !self.strata.is_empty() {
+            self.sample_stratified(config);
+        } else if config.example_replacement {
+            let n = self.inner.data.nexamples(); // the buffer is longer when over-sampling
+            sample_replacement(&mut self.inner.rng, n, &mut self.index_buffer[0..k]);
+        } else {
+            shuffle_first_k(&mut self.inner.rng, k, &mut self.index_buffer);
+        }
+    }
+
+    /// Sample the negative and positive examples separately, so that the number of examples of
+    /// each class in the bag is fixed.
+    fn sample_stratified(&mut self, config: &Config) {
+        let sizes = stratified_sizes(config, self.inner.data);
+        let rng = &mut self.inner.rng;
+        let mut offset = 0;
+        for (class, &k) in self.strata.iter().zip(&sizes) {
+            let bag = &mut self.index_buffer[offset..offset + k];
+            if config.example_replacement {
+                let n = class.len();
+                bag.iter_mut().for_each(|i| *i = class[rng.gen_range(0, n)]);
+            } else {
+                let mut buffer = class.clone();
+                shuffle_first_k(rng, k, &mut buffer);
+                bag.copy_from_slice(&buffer[0..k]);
+            }
+            offset += k;
         }
+        self.index_buffer[0..offset].sort_unstable();
     }
 
-    fn sample_examples(&mut self) {
+    /// Gradient-based one-side sampling: the examples with the largest absolute gradients are
+    /// always active, the others are sampled uniformly and get a larger weight.
+    fn sample_goss(&mut self, config: &Config, grad: &[NumT], (lo, hi): (NumT, NumT)) {
+        let n = self.index_buffer.len();
         let k = self.nactive_examples();
-        shuffle_first_k(&mut self.inner.rng, k, &mut self.index_buffer);
+        let ntop = ((n as NumT * config.top_rate).round() as usize).min(k);
+        let weight = (1.0 - config.top_rate) / config.other_rate;
+        let abs_grad = |i: usize| grad[i].max(lo).min(hi).abs();
+
+        // largest gradients first, ties broken by index so that the order does not depend on the
+        // previous sample
+        self.index_buffer.sort_unstable_by(|&i, &j| {
+            abs_grad(j).partial_cmp(&abs_grad(i)).unwrap_or(Ordering::Equal).then(i.cmp(&j))
+        });
+        let threshold = if ntop < n { abs_grad(self.index_buffer[ntop]) } else { 0.0 };
+        shuffle_first_k(&mut self.inner.rng, k - ntop, &mut self.index_buffer[ntop..]);
+
+        self.example_weights.clear();
+        self.example_weights.resize(n, 0.0);
+        for &i in &self.index_buffer[0..ntop] { self.example_weights[i] = 1.0; }
+        for &i in &self.index_buffer[ntop..k] { self.example_weights[i] = weight; }
+        self.goss_bound = threshold * weight;
+        self.index_buffer[0..k].sort_unstable();
     }
 
     fn active_examples_noborrow<'b>(index_buffer: &'b [usize], nactive_examples: usize) -> &'b [usize] {
@@ -351,56 +489,39 @@ impl <'a> Dataset<'a> {
 
     pub fn update(&mut self, config: &Config, grad: &[NumT], grad_bounds: (NumT, NumT)) {
         self.update_count += 1;
-        self.inner.initialize_supercats();
 
         // only fully update every `config.sample_freq` updates
         if config.sample_freq == 0 || (self.update_count > 1 &&
                                        self.update_count % config.sample_freq != 0) { return; }
 
+        // the bitvecs are reused until the next full update: remember how to recompute them
+        if config.sample_freq > 1 {
+            let mut state = self.sampling_state();
+            state.update_count -= 1;
+            self.last_full_update = Some(FullUpdate {
+                state,
+                gradients: grad.to_vec(),
+                grad_bounds,
+            });
+        }
+
         self.inner.sample_features();
 
         // Example sampling
         if self.example_sampling_enabled() {
-            self.sample_examples();
+            self.sample_examples(config, grad, grad_bounds);
 
             let (buf, nactive) = (&self.index_buffer, self.nactive_examples());
             let active_examples = Self::active_examples_noborrow(buf, nactive);
             let examples = SliceIntoIter(active_examples);
-
-            for u in 0..self.nactive_features() {
-                let feat_id = self.inner.useful_features[u]; // [0..nactive_features] are active
-                match self.inner.data.feat_type(feat_id) {
-                    FeatType::LoCardCat => {
-                        self.inner.update_locard_cat(config, feat_id, &examples);
-                    },
-                    FeatType::HiCardCat => {
-                        self.inner.update_hicard_cat(config, feat_id, &examples, grad,
-                                                     grad_bounds);
-                    },
-                    FeatType::Numerical => {
-                        self.inner.update_num(config, feat_id, &examples, grad);
-                    },
-                }
-            }
+            self.inner.update_features(config, &examples, grad, grad_bounds, true);
         }
-        
-        // No example sampling -- only if there are non-locard categorical features
+
+        // No example sampling -- only if there are non-locard categorical features; can reuse
+        // the locard-cat bitvecs from initialization
         else if self.inner.has_non_locard_cat_features {
             let examples = RangeIntoIter(self.inner.data.nexamples());
-            for u in 0..self.nactive_features() {
-                let feat_id = self.inner.useful_features[u]; // [0..nactive_features] are active
-
-                match self.inner.data.feat_type(feat_id) {
-                    FeatType::LoCardCat => {}, // can reuse bitvecs from initialization
-                    FeatType::HiCardCat => {
-                        self.inner.update_hicard_cat(config, feat_id, &examples, grad,
-                                                     grad_bounds);
-                    },
-                    FeatType::Numerical => {
-                        self.inner.update_num(config, feat_id, &examples, grad)
-                    },
-                }
-            }
+            self.inner.update_features(config, &examples, grad, grad_bounds, false);
         }
     }
 
@@ -412,6 +533,18 @@ impl <'a> Dataset<'a> {
     pub fn nactive_examples(&self) -> usize { self.inner.nactive_examples }
     pub fn active_features(&self) -> &[usize] { &self.inner.active_features() }
 
+    /// The GOSS weight of each example, if GOSS is enabled.
+    pub fn example_weights(&self) -> Option<&[NumT]> {
+        if self.example_weights.is_empty() { None } else { Some(&self.example_weights) }
+    }
+
+    /// Bounds for the discretized gradients: with GOSS, the weighted gradients of the sampled
+    /// small-gradient examples can exceed the bounds of the objective.
+    pub fn grad_bounds(&self, (lo, hi): (NumT, NumT)) -> (NumT, NumT) {
+        if self.example_weights.is_empty() { (lo, hi) }
+        else { (lo.min(-self.goss_bound), hi.max(self.goss_bound)) }
+    }
+
     pub fn map_index(&self, local_index: usize) -> usize {
         debug_assert!(self.example_sampling_enabled()); // bagged index -> global data index
         self.active_examples()[local_index]
@@ -436,35 +569,152 @@ impl <'a> Dataset<'a> {
     }
 
     pub fn get_nbins(&self, feat_id: usize) -> usize {
-        self.inner.used_nbins[feat_id]
+        self.inner.features[feat_id].used_nbins
     }
 
     pub fn get_bitvec(&self, feat_id: usize, split_id: usize) -> BitVecRef {
-        let range = self.inner.bitvecs[feat_id][split_id];
-        self.inner.store.get_bitvec(range)
+        let feature = &self.inner.features[feat_id];
+        feature.store.get_bitvec(feature.bitvecs[split_id])
     }
 
     pub fn get_split_value(&self, feat_id: usize, split_id: usize) -> NumT {
         match self.inner.data.feat_type(feat_id) {
             FeatType::LoCardCat => split_id as NumT,
             FeatType::HiCardCat => split_id as NumT, // == super-category
-            FeatType::Numerical => self.inner.split_candidates[feat_id][split_id],
+            FeatType::Numerical => self.inner.features[feat_id].split_candidates[split_id],
         }
     }
 
-    pub fn get_supercats(&mut self) -> &Vec<Vec<CatT>> {
-        //assert!(!self.inner.supercats.is_empty(), "not yet updated?");
-        //let mut tmp = Vec::new();
-        //std::mem::swap(&mut tmp, &mut self.inner.supercats);
-        //debug_assert!(!tmp.is_empty());
-        //debug_assert!(self.inner.supercats.is_empty());
-        //tmp
-        &self.inner.supercats
+    /// The super-category of each category of each high-cardinality categorical feature.
+    pub fn get_supercats(&self) -> Vec<Vec<CatT>> {
+        self.inner.features.iter().map(|f| f.supercats.clone()).collect()
     }
     
     pub fn data(&self) -> &Data {
         self.inner.data
     }
+
+    // ------------
+
+    fn sampling_state(&self) -> SamplingState {
+        SamplingState {
+            seed: self.inner.rng.seed,
+            ndraws: self.inner.rng.ndraws,
+            useful_features: self.inner.useful_features.clone(),
+            index_buffer: self.index_buffer.clone(),
+            update_count: self.update_count,
+        }
+    }
+
+    fn set_sampling_state(&mut self, state: &SamplingState) {
+        if self.inner.rng.ndraws != state.ndraws {
+            self.inner.rng = CountingRng::restore(state.seed, state.ndraws);
+        }
+        self.inner.useful_features.copy_from_slice(&state.useful_features);
+        self.index_buffer.copy_from_slice(&state.index_buffer);
+        self.update_count = state.update_count;
+    }
+
+    pub fn checkpoint(&self) -> DatasetCheckpoint {
+        DatasetCheckpoint {
+            state: self.sampling_state(),
+            last_full_update: self.last_full_update.clone(),
+        }
+    }
+
+    /// Restore the sampling state of a checkpoint. If the current bitvecs stem from an earlier
+    /// update (`sample_freq` > 1), that update is recomputed.
+    pub fn restore(&mut self, config: &Config, checkpoint: &DatasetCheckpoint)
+        -> Result<(), String>
+    {
+        let state = &checkpoint.state;
+        if state.seed != config.random_seed {
+            return Err(format!("checkpoint: random_seed {} does not match configuration",
+                               state.seed));
+        }
+        if state.useful_features.len() != self.inner.useful_features.len()
+            || state.index_buffer.len() != self.index_buffer.len()
+        {
+            return Err(String::from("checkpoint: incompatible data or sampling configuration"));
+        }
+
+        if let Some(full) = &checkpoint.last_full_update {
+            self.set_sampling_state(&full.state);
+            self.update(config, &full.gradients, full.grad_bounds);
+        }
+        self.set_sampling_state(state);
+        Ok(())
+    }
+}
+
+/// The sampling state of a `Dataset`.
+#[derive(Clone)]
+struct SamplingState {
+    seed: u64,
+    ndraws: u64,
+    useful_features: Vec<usize>,
+    index_buffer: Vec<usize>,
+    update_count: usize,
+}
+
+/// Sampling state before, and gradients used by the last full update.
+#[derive(Clone)]
+struct FullUpdate {
+    state: SamplingState,
+    gradients: Vec<NumT>,
+    grad_bounds: (NumT, NumT),
+}
+
+/// The part of a training checkpoint that concerns the `Dataset`.
+pub struct DatasetCheckpoint {
+    state: SamplingState,
+    last_full_update: Option<FullUpdate>,
+}
+
+impl SamplingState {
+    fn write<W: Write>(&self, w: &mut CheckpointWriter<W>) -> IoResult<()> {
+        w.write_u64(self.seed)?;
+        w.write_u64(self.ndraws)?;
+        w.write_usizes(&self.useful_features)?;
+        w.write_usizes(&self.index_buffer)?;
+        w.write_usize(self.update_count)
+    }
+
+    fn read<R: Read>(r: &mut CheckpointReader<R>) -> IoResult<SamplingState> {
+        Ok(SamplingState {
+            seed: r.read_u64()?,
+            ndraws: r.read_u64()?,
+            useful_features: r.read_usizes()?,
+            index_buffer: r.read_usizes()?,
+            update_count: r.read_usize()?,
+        })
+    }
+}
+
+impl DatasetCheckpoint {
+    pub(crate) fn write<W: Write>(&self, w: &mut CheckpointWriter<W>) -> IoResult<()> {
+        self.state.write(w)?;
+        w.write_bool(self.last_full_update.is_some())?;
+        if let Some(full) = &self.last_full_update {
+            full.state.write(w)?;
+            w.write_numts(&full.gradients)?;
+            w.write_numt(full.grad_bounds.0)?;
+            w.write_numt(full.grad_bounds.1)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn read<R: Read>(r: &mut CheckpointReader<R>) -> IoResult<DatasetCheckpoint> {
+        let state = SamplingState::read(r)?;
+        let last_full_update = if r.read_bool()? {
+            Some(FullUpdate {
+                state: SamplingState::read(r)?,
+                gradients: r.read_numts()?,
+                grad_bounds: (r.read_numt()?, r.read_numt()?),
+            })
+        } else { None };
+        Ok(DatasetCheckpoint { state, last_full_update })
+    }
 }
 
 
@@ -476,6 +726,48 @@ impl <'a> Dataset<'a> {
 
 // ------------------------------------------------------------------------------------------------
 
+/// `SmallRng` that counts the values it has drawn, so that its state can be restored by re-seeding
+/// and skipping that many values. Everything is drawn using `next_u64`.
+#[derive(Clone)]
+pub(crate) struct CountingRng {
+    rng: SmallRng,
+    seed: u64,
+    ndraws: u64,
+}
+
+impl CountingRng {
+    pub(crate) fn new(seed: u64) -> CountingRng {
+        CountingRng { rng: SmallRng::seed_from_u64(seed), seed, ndraws: 0 }
+    }
+
+    pub(crate) fn restore(seed: u64, ndraws: u64) -> CountingRng {
+        let mut rng = CountingRng::new(seed);
+        for _ in 0..ndraws { rng.next_u64(); }
+        rng
+    }
+
+    pub(crate) fn seed(&self) -> u64 { self.seed }
+    pub(crate) fn ndraws(&self) -> u64 { self.ndraws }
+}
+
+impl RngCore for CountingRng {
+    fn next_u32(&mut self) -> u32 { self.next_u64() as u32 }
+    fn next_u64(&mut self) -> u64 {
+        self.ndraws += 1;
+        self.rng.next_u64()
+    }
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[0..chunk.len()]);
+        }
+    }
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), RandError> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
 struct RangeIntoIter(usize);
 struct SliceIntoIter<'a>(&'a [usize]);
 
@@ -500,9 +792,13 @@ pub struct InactiveExampleIter<'b> {
 impl <'b> Iterator for InactiveExampleIter<'b> {
     type Item = usize;
     fn next(&mut self) -> Option<usize> {
-        while self.active_examples.get(0).map_or(false, |&i| i == self.index) {
+        loop { // active examples can contain duplicates when sampling with replacement
+            match self.active_examples.get(0) {
+                Some(&i) if i < self.index => {},
+                Some(&i) if i == self.index => { self.index += 1; },
+                _ => break,
+            }
             self.active_examples = &self.active_examples[1..];
-            self.index += 1;
         }
         if self.index < self.n {
             let tmp = self.index;
@@ -512,13 +808,21 @@ impl <'b> Iterator for InactiveExampleIter<'b> {
     }
 }
 
-#[allow(dead_code)]
-fn sample_replacement(rng: &mut SmallRng, n: usize, buffer: &mut [usize]) {
+fn sample_replacement<R: Rng>(rng: &mut R, n: usize, buffer: &mut [usize]) {
     buffer.iter_mut().for_each(|i| *i = rng.gen_range(0, n));
     buffer.sort_unstable();
 }
 
-fn shuffle_first_k(rng: &mut SmallRng, k: usize, buffer: &mut [usize]) {
+/// Number of negative and positive examples in a stratified sample.
+fn stratified_sizes(config: &Config, data: &Data) -> [usize; 2] {
+    let npos = data.get_target().iter().filter(|&&t| t > 0.5).count();
+    let nneg = data.nexamples() - npos;
+    let fraction = |f: NumT| if f < 0.0 { config.example_fraction } else { f };
+    [(nneg as NumT * fraction(config.neg_example_fraction)).round() as usize,
+     (npos as NumT * fraction(config.pos_example_fraction)).round() as usize]
+}
+
+pub(crate) fn shuffle_first_k<R: Rng>(rng: &mut R, k: usize, buffer: &mut [usize]) {
     let n = buffer.len();
     for i in 0..k {
         let j = rng.gen_range(i, n);
@@ -529,7 +833,7 @@ fn shuffle_first_k(rng: &mut SmallRng, k: usize, buffer: &mut [usize]) {
 }
 
 #[allow(dead_code)]
-fn reservoir_sample(rng: &mut SmallRng, n: usize, buffer: &mut [usize]) {
+fn reservoir_sample<R: Rng>(rng: &mut R, n: usize, buffer: &mut [usize]) {
     let k = buffer.len();
     debug_assert!(n > k);
     for i in 0..n {
@@ -584,10 +888,9 @@ mod test {
         assert_eq!(dataset.active_features(), &[0, 1]); // dependent on seed
         assert_eq!(dataset.active_examples(), &[0, 1, 3, 5, 6, 7]); // depends on seed
 
-        let ranges = &dataset.inner.bitvecs[1]; // locard-cat feature
         let values = vec![0b100001, 0b110, 0b11000];
         for i in 0..3 {
-            let bitvec = dataset.inner.store.get_bitvec(ranges[i]);
+            let bitvec = dataset.get_bitvec(1, i); // locard-cat feature
             let x = bitvec.cast::<u32>()[0];
             println!("{:3}: {:032b}", i, x);
             assert_eq!(values[i], x);
@@ -609,7 +912,6 @@ mod test {
         let mut dataset = Dataset::new(&config, &data);
         dataset.update(&config, target, target_lims);
 
-        let ranges = &dataset.inner.bitvecs[0];
         let values = vec![0b00000000000000000000000000001111u32,
                           0b00000000000000000000000011111111,
                           0b00000000000000000000111111111111,
@@ -618,7 +920,7 @@ mod test {
                           0b00000000111111111111111111111111,
                           0b00001111111111111111111111111111];
         for i in 0..dataset.get_nbins(0) {
-            let bitvec = dataset.inner.store.get_bitvec(ranges[i]);
+            let bitvec = dataset.get_bitvec(0, i);
             let x = bitvec.cast::<u32>()[0];
             println!("{:3}: {:032b}", i, x);
             assert_eq!(values[i], x);
@@ -655,9 +957,8 @@ mod test {
 
         //dbg!(&data.features);
 
-        let ranges = &dataset.inner.bitvecs[0];
         for i in 0..dataset.get_nbins(0) {
-            let bitvec = dataset.inner.store.get_bitvec(ranges[i]);
+            let bitvec = dataset.get_bitvec(0, i);
             let x = bitvec.cast::<u32>()[0];
             println!("{:3}: {:032b}", i, x);
             assert_eq!(values[i], x);
@@ -745,9 +1046,8 @@ mod test {
 
         for k in 0..3 {
             println!("== feature {}", k);
-            let ranges = &dataset.inner.bitvecs[k];
             for i in 0..dataset.get_nbins(k) {
-                let bitvec = dataset.inner.store.get_bitvec(ranges[i]);
+                let bitvec = dataset.get_bitvec(k, i);
                 let x = bitvec.cast::<u64>()[0];
                 println!("{:3}: {:064b}", i, x);
                 assert_eq!(values[k * 8 + i], x);
@@ -756,6 +1056,71 @@ mod test {
         }
     }
 
+    #[test]
+    fn goss() {
+        let mut config = Config::new();
+        config.csv_has_header = false;
+        config.example_sampling = String::from("goss");
+        config.top_rate = 0.2;
+        config.other_rate = 0.25;
+
+        let d: String = (0..20).map(|i| format!("{},0\n", i)).collect();
+        let data = Data::from_csv(&config, &d).unwrap();
+        let grad: Vec<NumT> = (0..20).map(|i| i as NumT - 10.0).collect();
+        let mut dataset = Dataset::new(&config, &data);
+        dataset.update(&config, &grad, (-10.0, 10.0));
+
+        assert_eq!(dataset.nactive_examples(), 9);
+        let active = dataset.active_examples();
+        assert!(active.windows(2).all(|w| w[0] < w[1]));
+
+        // 4 largest |grad|: ties broken by index, so 18 is not included
+        let weights = dataset.example_weights().unwrap();
+        let top: Vec<usize> = (0..20).filter(|&i| weights[i] == 1.0).collect();
+        assert_eq!(top, vec![0, 1, 2, 19]);
+        assert!(active.iter().all(|&i| weights[i] > 0.0));
+        assert_eq!(active.iter().filter(|&&i| weights[i] == 3.2).count(), 5);
+        assert_eq!(weights.iter().filter(|&&w| w == 0.0).count(), 11);
+        assert_eq!(dataset.grad_bounds((-10.0, 10.0)), (-25.6, 25.6)); // 8 * 3.2
+    }
+
+    #[test]
+    fn stratified() {
+        let new_config = || {
+            let mut config = Config::new();
+            config.csv_has_header = false;
+            config.example_sampling = String::from("stratified");
+            config.example_fraction = 0.5;
+            config
+        };
+        let config = new_config();
+        let d: String = (0..100).map(|i| format!("{},{}\n", i, (i % 10 == 3) as u8)).collect();
+        let data = Data::from_csv(&config, &d).unwrap();
+        let target = data.get_target();
+        let npos = |dataset: &Dataset| {
+            dataset.active_examples().iter().filter(|&&i| target[i] == 1.0).count()
+        };
+
+        // keep the class ratio
+        let mut dataset = Dataset::new(&config, &data);
+        for _ in 0..3 {
+            dataset.update(&config, target, (0.0, 1.0));
+            assert_eq!(dataset.nactive_examples(), 50);
+            assert_eq!(npos(&dataset), 5);
+            assert!(dataset.active_examples().windows(2).all(|w| w[0] < w[1]));
+        }
+
+        // over-sample the positive class
+        let mut config = new_config();
+        config.example_replacement = true;
+        config.pos_example_fraction = 2.0;
+        config.neg_example_fraction = 0.2;
+        let mut dataset = Dataset::new(&config, &data);
+        dataset.update(&config, target, (0.0, 1.0));
+        assert_eq!(dataset.nactive_examples(), 38);
+        assert_eq!(npos(&dataset), 20);
+    }
+
     #[test]
     fn inactive_examples_iter() {
         let v = vec![2,3,5,6,9];
@@ -779,5 +1144,13 @@ mod test {
             n: 9
         };
         assert_eq!(&iter.collect::<Vec<usize>>(), &[1,4,7,8]);
+
+        let v = vec![0,0,2,2,2,3,6,6];
+        let iter = InactiveExampleIter {
+            active_examples: &v,
+            index: 0,
+            n: 8
+        };
+        assert_eq!(&iter.collect::<Vec<usize>>(), &[1,4,5,7]);
     }
 }
@@ -30,5 +30,7 @@ pub mod tree;
 pub mod objective;
 pub mod binner;
 pub mod metric;
+pub mod callback;
+pub mod checkpoint;
 pub mod boost;
 pub mod c_api;
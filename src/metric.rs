@@ -4,12 +4,16 @@
  * Author: Laurens Devos
 */
 
-use crate::{NumT};
+use crate::{NumT, EPSILON};
+use crate::config::Config;
 
 
 
 macro_rules! impl_metric {
     ($type:ty, eval_one: $impl:expr) => {
+        impl_metric!($type, eval_one: $impl, higher_is_better: false);
+    };
+    ($type:ty, eval_one: $impl:expr, higher_is_better: $hib:expr) => {
         impl_metric!($type, eval_all: |this: &$type, targets: &[NumT], preds: &[NumT]| -> NumT {
             let mut loss = 0.0;
             let mut count = 0;
@@ -18,39 +22,56 @@ macro_rules! impl_metric {
                 count += 1;
             }
             loss / count as NumT
-        });
+        }, higher_is_better: $hib);
     };
     ($type:ty, eval_all: $impl:expr) => {
+        impl_metric!($type, eval_all: $impl, higher_is_better: false);
+    };
+    ($type:ty, eval_all: $impl:expr, higher_is_better: $hib:expr) => {
         impl Metric for $type {
-            fn name(&self) -> &'static str { stringify!($type) }
+            fn name(&self) -> &str { stringify!($type) }
             fn eval(&self, targets: &[NumT], predictions: &[NumT]) -> NumT {
                 $impl(self, targets, predictions)
             }
+            fn higher_is_better(&self) -> bool { $hib }
         }
     }
 }
 
 
 pub trait Metric {
-    fn name(&self) -> &'static str;
+    fn name(&self) -> &str;
     fn eval(&self, targets: &[NumT], predictions: &[NumT]) -> NumT;
+
+    /// Whether larger values of this metric indicate a better model (e.g. AUC), or smaller values
+    /// (e.g. a loss).
+    fn higher_is_better(&self) -> bool { false }
 }
 
-pub fn metric_from_name(name: &str) -> Option<Box<dyn Metric>> {
+pub fn metric_from_name(name: &str, config: &Config) -> Option<Box<dyn Metric>> {
+    let threshold = config.metric_threshold;
     match name.to_lowercase().as_str() {
         "l2" => Some(Box::new(L2::new())),
         "rmse" => Some(Box::new(Rmse::new())),
+        "mae" | "l1" => Some(Box::new(Mae::new())),
+        "mape" => Some(Box::new(Mape::new())),
+        "r2" => Some(Box::new(R2::new())),
         "binaryloss" | "binary_loss" => Some(Box::new(BinaryLoss::new())),
         "binaryerror" | "binary_error" => Some(Box::new(BinaryError::new())),
         "binaryerror01" | "binary_error01" => Some(Box::new(BinaryError01::new())),
+        "logloss" | "log_loss" => Some(Box::new(LogLoss::new())),
+        "accuracy" => Some(Box::new(Accuracy::new(threshold))),
+        "f1" => Some(Box::new(F1::new(threshold))),
+        "auc" | "roc_auc" => Some(Box::new(RocAuc::new())),
+        "pr_auc" | "average_precision" => Some(Box::new(PrAuc::new())),
         _ => None
     }
 }
 
-pub fn metrics_from_names(names: &[String]) -> Option<Vec<Box<dyn Metric>>> {
+pub fn metrics_from_names(names: &[String], config: &Config) -> Option<Vec<Box<dyn Metric>>> {
     let mut metrics = Vec::new();
     for name in names {
-        match metric_from_name(name) {
+        match metric_from_name(name, config) {
             Some(metric) => metrics.push(metric),
             None => return None,
         }
@@ -137,3 +158,217 @@ impl_metric!(BinaryError01, eval_one: |_, t: NumT, p: NumT| {
 impl BinaryError01 {
     pub fn new() -> BinaryError01 { BinaryError01 {} }
 }
+
+// ------------------------------------------------------------------------------------------------
+
+pub struct Mae {}
+impl_metric!(Mae, eval_one: |_, t: NumT, p: NumT| {
+    (t - p).abs()
+});
+
+impl Mae {
+    pub fn new() -> Mae { Mae {} }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+pub struct Mape {} // examples with a zero target are skipped
+impl_metric!(Mape, eval_all: |_, ts: &[NumT], ps: &[NumT]| {
+    let mut loss = 0.0;
+    let mut count = 0;
+    for (&t, &p) in ts.iter().zip(ps) {
+        if t == 0.0 { continue; }
+        loss += ((t - p) / t).abs();
+        count += 1;
+    }
+    if count == 0 { 0.0 } else { loss / count as NumT }
+});
+
+impl Mape {
+    pub fn new() -> Mape { Mape {} }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+pub struct R2 {}
+impl_metric!(R2, eval_all: |_, ts: &[NumT], ps: &[NumT]| {
+    let n = ts.len() as NumT;
+    let mean = ts.iter().fold(0.0, |x, y| x+y) / n;
+    let (mut ss_res, mut ss_tot) = (0.0, 0.0);
+    for (&t, &p) in ts.iter().zip(ps) {
+        ss_res += (t - p) * (t - p);
+        ss_tot += (t - mean) * (t - mean);
+    }
+    if ss_tot == 0.0 { 0.0 } else { 1.0 - ss_res / ss_tot }
+}, higher_is_better: true);
+
+impl R2 {
+    pub fn new() -> R2 { R2 {} }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+pub struct LogLoss {} // predictions are raw margins, as for BinaryLoss
+impl_metric!(LogLoss, eval_one: |_, t: NumT, p: NumT| {
+    let p = 1.0 / (1.0 + (-2.0 * p).exp()); // probability of the positive class
+    let p = p.max(EPSILON).min(1.0 - EPSILON);
+    if t < 0.5 { -(1.0 - p).ln() }
+    else       { -p.ln() }
+});
+
+impl LogLoss {
+    pub fn new() -> LogLoss { LogLoss {} }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+pub struct Accuracy { threshold: NumT }
+impl_metric!(Accuracy, eval_one: |this: &Accuracy, t: NumT, p: NumT| {
+    let pos_target = t >= 0.5;
+    let pos_pred = p >= this.threshold;
+    if pos_target == pos_pred { 1.0 } else { 0.0 }
+}, higher_is_better: true);
+
+impl Accuracy {
+    pub fn new(threshold: NumT) -> Accuracy { Accuracy { threshold } }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+pub struct F1 { threshold: NumT }
+impl_metric!(F1, eval_all: |this: &F1, ts: &[NumT], ps: &[NumT]| {
+    let (mut tp, mut fp, mut fn_) = (0, 0, 0);
+    for (&t, &p) in ts.iter().zip(ps) {
+        match (t >= 0.5, p >= this.threshold) {
+            (true, true)   => tp += 1,
+            (false, true)  => fp += 1,
+            (true, false)  => fn_ += 1,
+            (false, false) => {},
+        }
+    }
+    if tp == 0 { 0.0 }
+    else { (2 * tp) as NumT / (2 * tp + fp + fn_) as NumT }
+}, higher_is_better: true);
+
+impl F1 {
+    pub fn new(threshold: NumT) -> F1 { F1 { threshold } }
+}
+
+// ------------------------------------------------------------------------------------------------
+
+/// Sort the examples by decreasing prediction and call `f(npos, nneg)` for each group of tied
+/// predictions, with `npos` and `nneg` the number of positive and negative examples in the group.
+fn for_each_tie_group<F>(targets: &[NumT], predictions: &[NumT], mut f: F)
+where F: FnMut(usize, usize) {
+    let mut order: Vec<usize> = (0..targets.len()).collect();
+    order.sort_unstable_by(|&i, &j| {
+        predictions[j].partial_cmp(&predictions[i]).expect("NaN prediction")
+    });
+
+    let mut k = 0;
+    while k < order.len() {
+        let (mut npos, mut nneg) = (0, 0);
+        let value = predictions[order[k]];
+        while k < order.len() && predictions[order[k]] == value {
+            if targets[order[k]] >= 0.5 { npos += 1; } else { nneg += 1; }
+            k += 1;
+        }
+        f(npos, nneg);
+    }
+}
+
+pub struct RocAuc {}
+impl_metric!(RocAuc, eval_all: |_, ts: &[NumT], ps: &[NumT]| {
+    // Mann-Whitney U statistic: ties count for one half
+    let (mut npos_seen, mut nneg_seen) = (0, 0);
+    let mut area = 0.0;
+    for_each_tie_group(ts, ps, |npos, nneg| {
+        area += (nneg * npos_seen) as f64 + 0.5 * (nneg * npos) as f64;
+        npos_seen += npos;
+        nneg_seen += nneg;
+    });
+    if npos_seen == 0 || nneg_seen == 0 { return 0.5; }
+    (area / (npos_seen as f64 * nneg_seen as f64)) as NumT
+}, higher_is_better: true);
+
+impl RocAuc {
+    pub fn new() -> RocAuc { RocAuc {} }
+}
+
+pub struct PrAuc {} // average precision
+impl_metric!(PrAuc, eval_all: |_, ts: &[NumT], ps: &[NumT]| {
+    let (mut npos_seen, mut nseen) = (0, 0);
+    let mut area = 0.0;
+    for_each_tie_group(ts, ps, |npos, nneg| {
+        npos_seen += npos;
+        nseen += npos + nneg;
+        area += npos as f64 * (npos_seen as f64 / nseen as f64);
+    });
+    if npos_seen == 0 { return 0.0; }
+    (area / npos_seen as f64) as NumT
+}, higher_is_better: true);
+
+impl PrAuc {
+    pub fn new() -> PrAuc { PrAuc {} }
+}
+
+
+
+
+
+
+
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::objective::objective_from_name;
+
+    #[test]
+    fn regression_metrics() {
+        let ts = [1.0, 2.0, 3.0, 4.0];
+        let ps = [1.5, 2.0, 2.0, 4.0];
+        assert_eq!(Mae::new().eval(&ts, &ps), 0.375);
+        assert_eq!(Mape::new().eval(&ts, &ps), (0.5 + 1.0 / 3.0) / 4.0);
+        assert_eq!(R2::new().eval(&ts, &ps), 1.0 - 1.25 / 5.0);
+        assert!(R2::new().higher_is_better());
+        assert!(!Mae::new().higher_is_better());
+    }
+
+    #[test]
+    fn classification_metrics() {
+        let ts = [0.0, 0.0, 1.0, 1.0, 1.0];
+        let ps = [0.1, 0.6, 0.4, 0.8, 0.9];
+        assert_eq!(Accuracy::new(0.5).eval(&ts, &ps), 0.6);
+        assert_eq!(Accuracy::new(0.3).eval(&ts, &ps), 0.8);
+        assert_eq!(F1::new(0.5).eval(&ts, &ps), 4.0 / 6.0);
+        assert!((LogLoss::new().eval(&[1.0, 0.0], &[0.0, 0.0]) - 0.5f32.ln().abs()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn logloss_binary_objective() {
+        let config = Config::new();
+        let ts = [1.0, 0.0, 1.0, 1.0];
+        let mut objective = objective_from_name("binary").unwrap();
+        objective.initialize(&config, &ts);
+
+        // the prior predicts the fraction of positive examples: 0.75
+        let expected = -(3.0 * 0.75f32.ln() + 0.25f32.ln()) / 4.0;
+        let logloss = LogLoss::new().eval(&ts, objective.predictions());
+        assert!((logloss - expected).abs() < 1e-6, "{} vs {}", logloss, expected);
+        let binary_loss = BinaryLoss::new().eval(&ts, objective.predictions());
+        assert!((logloss - binary_loss).abs() < 1e-6);
+    }
+
+    #[test]
+    fn auc() {
+        let ts = [0.0, 0.0, 1.0, 1.0, 1.0];
+        let ps = [0.1, 0.6, 0.4, 0.8, 0.9];
+        assert_eq!(RocAuc::new().eval(&ts, &ps), 5.0 / 6.0);
+        assert_eq!(RocAuc::new().eval(&ts, &[0.5; 5]), 0.5);
+        assert_eq!(PrAuc::new().eval(&ts, &ps), (1.0 + 1.0 + 0.75) / 3.0);
+        assert_eq!(PrAuc::new().eval(&[0.0, 1.0], &[1.0, 0.0]), 0.5);
+    }
+}
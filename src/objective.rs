@@ -4,6 +4,9 @@
  * Author: Laurens Devos
 */
 
+use log::info;
+use rayon::prelude::*;
+
 use crate::{NumT, EPSILON, POS_INF, NEG_INF};
 use crate::config::Config;
 use crate::binner::Binner;
@@ -30,6 +33,15 @@ pub trait Objective: Send + Sync {
     /// prediction to its bias (-> use initialize_base).
     fn initialize(&mut self, config: &Config, targets: &[NumT]);
 
+    /// Initialize the objective with an initial score (offset) for each example. The bias is
+    /// computed relative to the offsets, and each prediction is set to its offset plus the bias.
+    fn initialize_with_offsets(&mut self, config: &Config, targets: &[NumT], offsets: &[NumT]);
+
+    /// Initialize the objective like `initialize`, but start from the given predictions instead of
+    /// the bias. This is used to continue training an existing model.
+    fn initialize_from_predictions(&mut self, config: &Config, targets: &[NumT],
+                                   predictions: &[NumT]);
+
     /// Update the gradients and related state of the objective so the next tree can be built.
     fn update(&mut self, targets: &[NumT]);
 
@@ -37,8 +49,16 @@ pub trait Objective: Send + Sync {
     /// predictions of the objective.
     fn predict_leaf_value(&mut self, targets: &[NumT], examples: &[usize]) -> NumT;
 
+    /// Like `predict_leaf_value`, but each example `i` has weight `weights[i]` (gradient-based
+    /// one-side sampling). The default implementation ignores the weights.
+    fn predict_leaf_value_weighted(&mut self, targets: &[NumT], examples: &[usize],
+                                   _weights: &[NumT]) -> NumT {
+        self.predict_leaf_value(targets, examples)
+    }
+
     /// Update the prediction of an out-of-bag example. In-bag examples are updated by
-    /// `predict_leaf_value`.
+    /// `predict_leaf_value`. This adds `value` to the prediction, so it is also used to add or
+    /// remove the contribution of existing trees (DART).
     fn update_out_of_bag_prediction(&mut self, i: usize, value: NumT);
 }
 
@@ -58,7 +78,9 @@ macro_rules! impl_simple_obj_methods {
         fn name(&self) -> &'static str   { stringify!($name) }
         fn gradients(&self) -> &[NumT]   { &self.gradients }
         fn predictions(&self) -> &[NumT] { &self.predictions }
-        fn bounds(&self) -> (NumT, NumT) { $bounds(self) }
+        fn bounds(&self) -> (NumT, NumT) {
+            self.gradient_bounds.bounds.unwrap_or_else(|| $bounds(self))
+        }
         fn bias(&self) -> NumT           { self.bias }
 
         fn update_out_of_bag_prediction(&mut self, i: usize, value: NumT) {
@@ -66,6 +88,13 @@ macro_rules! impl_simple_obj_methods {
             safety_check!(self.predictions[i].is_finite());
             self.predictions[i] += value;
         }
+
+        fn initialize_from_predictions(&mut self, config: &Config, targets: &[NumT],
+                                       predictions: &[NumT]) {
+            assert_eq!(targets.len(), predictions.len());
+            self.initialize(config, targets);
+            self.predictions.copy_from_slice(predictions);
+        }
     }
 }
 
@@ -76,6 +105,7 @@ macro_rules! objective_struct {
             bias: NumT,
             predictions: Vec<NumT>,
             gradients: Vec<NumT>,
+            gradient_bounds: GradientBounds,
             $(
                 $field: $type,
             )*
@@ -88,6 +118,7 @@ macro_rules! objective_struct {
                     bias: 0.0,
                     predictions: Vec::new(),
                     gradients: Vec::new(),
+                    gradient_bounds: GradientBounds::new(),
                     $(
                         $field: $init,
                     )*
@@ -101,6 +132,20 @@ macro_rules! objective_struct {
                 self.gradients.clear();
                 self.predictions.resize(n, bias);
                 self.gradients.resize(n, 0.0);
+                self.gradient_bounds.initialize(config);
+            }
+
+            /// Like `initialize_base`, but the predictions start at `offset + bias`.
+            fn initialize_offsets(&mut self, config: &Config, offsets: &[NumT], bias: NumT) {
+                self.initialize_base(config, offsets.len(), 0.0);
+                self.predictions.copy_from_slice(offsets);
+                self.shift_bias(bias);
+            }
+
+            /// Add `delta` to the bias and to each prediction.
+            fn shift_bias(&mut self, delta: NumT) {
+                self.bias += delta;
+                self.predictions.iter_mut().for_each(|p| *p += delta);
             }
 
             /// Update the current predictions. `predict_leaf_value` should always call this and
@@ -137,7 +182,7 @@ macro_rules! quantile {
             binner.insert(value, 1, |x, y| *x += y);
         }
 
-        let rank = ((count as NumT) * $q).floor() as u32;
+        let rank = ((count as NumT) * $q).floor() as u64;
         let bin = binner.bin_with_rank(rank, |&x| x);
         binner.bin_representative(bin + 1)
     }}
@@ -149,6 +194,103 @@ macro_rules! median {
     }}
 }
 
+/// Min and max value of the residuals `targets - predictions`.
+fn residual_limits(targets: &[NumT], predictions: &[NumT]) -> (NumT, NumT) {
+    targets.par_iter()
+        .zip(predictions.par_iter())
+        .with_min_len(PAR_MIN_EXAMPLES)
+        .map(|(&t, &p)| (t - p, t - p))
+        .reduce(|| (POS_INF, NEG_INF), |(min0, max0), (min1, max1)| {
+            (NumT::min(min0, min1), NumT::max(max0, max1))
+        })
+}
+
+/// Set each gradient to `f(target, prediction)`, in parallel.
+fn update_gradients<F>(gradients: &mut [NumT], targets: &[NumT], predictions: &[NumT], f: F)
+where F: Fn(NumT, NumT) -> NumT + Sync
+{
+    gradients.par_iter_mut()
+        .zip(targets.par_iter().zip(predictions.par_iter()))
+        .with_min_len(PAR_MIN_EXAMPLES)
+        .for_each(|(g, (&t, &p))| *g = f(t, p));
+}
+
+/// Selects the bounds of the discretized gradients according to the `gradient_bounds` strategy.
+/// `bounds` is `None` for the `objective` strategy, which leaves the bounds to the objective.
+struct GradientBounds {
+    strategy: String,
+    quantile: NumT,
+    bounds: Option<(NumT, NumT)>,
+    bins: Vec<u64>,
+}
+
+impl GradientBounds {
+    fn new() -> GradientBounds {
+        GradientBounds {
+            strategy: String::from("objective"),
+            quantile: 0.0,
+            bounds: None,
+            bins: Vec::new(),
+        }
+    }
+
+    fn initialize(&mut self, config: &Config) {
+        match config.gradient_bounds.as_str() {
+            "objective" | "symmetric" | "asymmetric" => {},
+            "percentile" => {
+                assert!(0.0 < config.gradient_bounds_quantile
+                        && config.gradient_bounds_quantile < 0.5,
+                        "gradient_bounds_quantile not in (0, 0.5)");
+                self.bins.resize(1024, 0);
+            },
+            s => panic!("unknown gradient_bounds '{}'", s),
+        }
+        self.strategy = config.gradient_bounds.clone();
+        self.quantile = config.gradient_bounds_quantile;
+        self.bounds = None;
+    }
+
+    /// Recompute the bounds from the new gradients.
+    fn update(&mut self, gradients: &[NumT]) {
+        if self.strategy == "objective" { return; }
+        let (min, max) = gradients.par_iter()
+            .with_min_len(PAR_MIN_EXAMPLES)
+            .map(|&g| (g, g))
+            .reduce(|| (POS_INF, NEG_INF), |(min0, max0), (min1, max1)| {
+                (NumT::min(min0, min1), NumT::max(max0, max1))
+            });
+        let bounds = match self.strategy.as_str() {
+            "symmetric" => {
+                let bound = NumT::min(min.abs(), max.abs());
+                (-bound, bound)
+            },
+            "percentile" if min < max => {
+                let (n, q) = (gradients.len(), self.quantile);
+                let lo = quantile!(of targets: q, self, (min, max), gradients, 0..n);
+                let hi = quantile!(of targets: 1.0 - q, self, (min, max), gradients, 0..n);
+                (lo, hi)
+            },
+            _ => (min, max),
+        };
+        self.bounds = Some(bounds);
+    }
+}
+
+/// The number of gradients outside `bounds`, i.e., the number of gradients that are clipped by
+/// the discretization.
+pub fn count_clipped(gradients: &[NumT], (lo, hi): (NumT, NumT)) -> usize {
+    gradients.par_iter()
+        .with_min_len(PAR_MIN_EXAMPLES)
+        .filter(|&&g| g < lo || g > hi)
+        .count()
+}
+
+/// Minimum number of examples processed by a single rayon task in the gradient updates.
+const PAR_MIN_EXAMPLES: usize = 4096;
+
+/// Number of Newton steps used to find the binary prior relative to per-example offsets.
+const BINARY_PRIOR_NEWTON_STEPS: usize = 10;
+
 
 
 
@@ -168,21 +310,23 @@ impl Objective for L2 {
         self.initialize_base(config, n, bias);
     }
 
+    fn initialize_with_offsets(&mut self, config: &Config, targets: &[NumT], offsets: &[NumT]) {
+        let n = targets.len();
+        let bias = targets.iter().zip(offsets).fold(0.0, |x, (t, o)| x + t - o) / n as NumT;
+        self.initialize_offsets(config, offsets, bias);
+    }
+
     fn update(&mut self, targets: &[NumT]) {
         let n = targets.len();
         assert_eq!(self.predictions.len(), n);
         assert_eq!(self.gradients.len(), n);
 
-        let (mut min, mut max) = (POS_INF, NEG_INF);
-        for i in 0..n {
-            let err = targets[i] - self.predictions[i];
-            min = NumT::min(min, err);
-            max = NumT::max(max, err);
-            self.gradients[i] = -err;
-        }
+        let (min, max) = residual_limits(targets, &self.predictions);
+        update_gradients(&mut self.gradients, targets, &self.predictions, |t, p| -(t - p));
 
         let bound = NumT::min(min.abs(), max.abs());
         self.bounds = (-bound, bound);
+        self.gradient_bounds.update(&self.gradients);
     }
 
     fn predict_leaf_value(&mut self, targets: &[NumT], examples: &[usize]) -> NumT {
@@ -193,6 +337,16 @@ impl Objective for L2 {
         let mean = sum / examples.len() as NumT;
         self.update_predictions(examples, mean)
     }
+
+    fn predict_leaf_value_weighted(&mut self, targets: &[NumT], examples: &[usize],
+                                   weights: &[NumT]) -> NumT {
+        let (mut sum, mut wsum) = (0.0, 0.0);
+        for &i in examples {
+            sum += weights[i] * (targets[i] - self.predictions[i]);
+            wsum += weights[i];
+        }
+        self.update_predictions(examples, sum / wsum)
+    }
 }
 
 
@@ -204,7 +358,7 @@ impl Objective for L2 {
 
 objective_struct!(L1 {
     limits: (NumT, NumT) = (0.0, 0.0),
-    bins: Vec<u32> = vec![0; 1024]
+    bins: Vec<u64> = vec![0; 1024]
 });
 
 impl Objective for L1 {
@@ -221,18 +375,22 @@ impl Objective for L1 {
         self.initialize_base(config, n, bias);
     }
 
+    fn initialize_with_offsets(&mut self, config: &Config, targets: &[NumT], offsets: &[NumT]) {
+        self.initialize_offsets(config, offsets, 0.0);
+        let n = targets.len();
+        let limits = residual_limits(targets, &self.predictions);
+        let bias = median!(of residuals: self, limits, targets, 0..n);
+        self.shift_bias(bias);
+    }
+
     fn update(&mut self, targets: &[NumT]) {
         let n = targets.len();
         assert_eq!(self.predictions.len(), n);
         assert_eq!(self.gradients.len(), n);
-        let (mut min, mut max) = (POS_INF, NEG_INF);
-        for i in 0..n {
-            let err = targets[i] - self.predictions[i];
-            min = err.min(min);
-            max = err.max(max);
-            self.gradients[i] = -err.signum();
-        }
-        self.limits = (min, max);
+        self.limits = residual_limits(targets, &self.predictions);
+        update_gradients(&mut self.gradients, targets, &self.predictions,
+                         |t, p| -(t - p).signum());
+        self.gradient_bounds.update(&self.gradients);
     }
 
     fn predict_leaf_value(&mut self, targets: &[NumT], examples: &[usize]) -> NumT {
@@ -253,7 +411,7 @@ objective_struct!(Huber {
     alpha: NumT = 0.0,
     delta: NumT = 0.0,
     limits: (NumT, NumT) = (0.0, 0.0),
-    bins: Vec<u32> = vec![0; 1024]
+    bins: Vec<u64> = vec![0; 1024]
 });
 
 impl Objective for Huber {
@@ -272,31 +430,33 @@ impl Objective for Huber {
         self.alpha = config.huber_alpha;
     }
 
+    fn initialize_with_offsets(&mut self, config: &Config, targets: &[NumT], offsets: &[NumT]) {
+        self.initialize_offsets(config, offsets, 0.0);
+        let n = targets.len();
+        let limits = residual_limits(targets, &self.predictions);
+        let bias = median!(of residuals: self, limits, targets, 0..n);
+        self.shift_bias(bias);
+        assert!(0.0 < config.huber_alpha && config.huber_alpha < 1.0);
+        self.alpha = config.huber_alpha;
+    }
+
     fn update(&mut self, targets: &[NumT]) {
         let n = targets.len();
         assert_eq!(self.predictions.len(), n);
         assert_eq!(self.gradients.len(), n);
 
         // determine delta = alpha-quantile { residuals }
-        let (mut min, mut max) = (POS_INF, NEG_INF);
-        for i in 0..n {
-            let err = targets[i] - self.predictions[i];
-            min = err.min(min);
-            max = err.max(max);
-        }
+        let (min, max) = residual_limits(targets, &self.predictions);
         self.limits = (min, max);
         self.delta = quantile!(of residuals: self.alpha, self, (min, max), targets, 0..n).abs();
 
         // set the gradients
-        for i in 0..n {
-            let err = targets[i] - self.predictions[i];
-            let err_abs = err.abs();
-            if err_abs <= self.delta {
-                self.gradients[i] = err;
-            } else {
-                self.gradients[i] = self.delta * err.signum();
-            }
-        }
+        let delta = self.delta;
+        update_gradients(&mut self.gradients, targets, &self.predictions, |t, p| {
+            let err = t - p;
+            if err.abs() <= delta { err } else { delta * err.signum() }
+        });
+        self.gradient_bounds.update(&self.gradients);
     }
 
     fn predict_leaf_value(&mut self, targets: &[NumT], examples: &[usize]) -> NumT {
@@ -338,7 +498,28 @@ impl Objective for Binary {
         self.initialize_base(config, n, prior);
         self.bound = config.binary_gradient_bound;
 
-        println!("[   ] binary objective: pos {}, neg {}, prior {}", npos, nneg, prior);
+        info!("[   ] binary objective: pos {}, neg {}, prior {}", npos, nneg, prior);
+    }
+
+    fn initialize_with_offsets(&mut self, config: &Config, targets: &[NumT], offsets: &[NumT]) {
+        debug_assert!(targets.iter().all(|&t| t == 0.0 || t == 1.0));
+        self.initialize_offsets(config, offsets, 0.0);
+        self.bound = config.binary_gradient_bound;
+
+        // no closed form for the prior given offsets: take a few Newton steps from 0.0
+        for _ in 0..BINARY_PRIOR_NEWTON_STEPS {
+            self.update(targets);
+            let (mut num, mut den) = (0.0, EPSILON);
+            for &g in &self.gradients {
+                let y = -g;
+                let yabs = y.abs();
+                num += y;
+                den += yabs * (2.0 - yabs);
+            }
+            self.shift_bias(num / den);
+        }
+
+        info!("[   ] binary objective: prior {} relative to offsets", self.bias);
     }
 
     fn update(&mut self, targets: &[NumT]) {
@@ -346,11 +527,11 @@ impl Objective for Binary {
         assert_eq!(self.predictions.len(), n);
         assert_eq!(self.gradients.len(), n);
 
-        for i in 0..n {
-            let (t, p) = (targets[i], self.predictions[i]);
+        update_gradients(&mut self.gradients, targets, &self.predictions, |t, p| {
             let y = 2.0 * t - 1.0; // 0.0 -> -1.0; 1.0 -> 1.0
-            self.gradients[i] = -(2.0 * y) / (1.0 + (2.0 * y * p).exp());
-        }
+            -(2.0 * y) / (1.0 + (2.0 * y * p).exp())
+        });
+        self.gradient_bounds.update(&self.gradients);
     }
 
     fn predict_leaf_value(&mut self, _: &[NumT], examples: &[usize]) -> NumT {
@@ -365,6 +546,21 @@ impl Objective for Binary {
         let value = num / den;
         self.update_predictions(examples, value)
     }
+
+    fn predict_leaf_value_weighted(&mut self, _: &[NumT], examples: &[usize], weights: &[NumT])
+        -> NumT
+    {
+        let mut num = 0.0;
+        let mut den = EPSILON;
+        for &i in examples {
+            let y = -self.gradients[i];
+            let yabs = y.abs();
+            num += weights[i] * y;
+            den += weights[i] * yabs * (2.0 - yabs);
+        }
+        let value = num / den;
+        self.update_predictions(examples, value)
+    }
 }
 
 
@@ -380,6 +576,10 @@ impl Objective for Hinge {
     fn initialize(&mut self, config: &Config, targets: &[NumT]) {
         debug_assert!(targets.iter().all(|&t| t == 0.0 || t == 1.0));
         assert_eq!(config.discr_nbits, 2, "Hinge loss requires 2 bits");
+        assert!(config.discr_nbits_schedule.is_empty()
+                && config.discr_nbits_error_threshold == 0.0,
+                "Hinge loss requires a fixed discr_nbits");
+        assert_eq!(config.gradient_bounds, "objective", "Hinge loss requires its own bounds");
         let n = targets.len();
 
         let nneg = targets.iter().filter(|&x| *x < 0.5).count() as NumT;
@@ -388,7 +588,20 @@ impl Objective for Hinge {
 
         self.initialize_base(config, n, prior);
 
-        println!("[   ] hinge objective: pos {}, neg {}, prior {}", npos, nneg, prior);
+        info!("[   ] hinge objective: pos {}, neg {}, prior {}", npos, nneg, prior);
+    }
+
+    fn initialize_with_offsets(&mut self, config: &Config, targets: &[NumT], offsets: &[NumT]) {
+        debug_assert!(targets.iter().all(|&t| t == 0.0 || t == 1.0));
+        assert_eq!(config.discr_nbits, 2, "Hinge loss requires 2 bits");
+        assert!(config.discr_nbits_schedule.is_empty()
+                && config.discr_nbits_error_threshold == 0.0,
+                "Hinge loss requires a fixed discr_nbits");
+        assert_eq!(config.gradient_bounds, "objective", "Hinge loss requires its own bounds");
+        let n = targets.len();
+        let prior = targets.iter().zip(offsets)
+            .fold(0.0, |x, (&t, &o)| x + if t < 0.5 { -1.0 } else { 1.0 } - o) / n as NumT;
+        self.initialize_offsets(config, offsets, prior);
     }
 
     fn update(&mut self, targets: &[NumT]) {
@@ -396,15 +609,13 @@ impl Objective for Hinge {
         assert_eq!(self.predictions.len(), n);
         assert_eq!(self.gradients.len(), n);
 
-        for i in 0..n {
-            let g = &mut self.gradients[i];
-            let (t, p) = (targets[i], self.predictions[i]);
+        update_gradients(&mut self.gradients, targets, &self.predictions, |t, p| {
             if t < 0.5 { // neg class
-                *g = if p > -1.0 { 1.0 } else { 0.0 };
+                if p > -1.0 { 1.0 } else { 0.0 }
             } else {     // pos class
-                *g = if p < 1.0 { -1.0 } else { 0.0 };
+                if p < 1.0 { -1.0 } else { 0.0 }
             }
-        }
+        });
     }
 
     fn predict_leaf_value(&mut self, targets: &[NumT], examples: &[usize]) -> NumT {
@@ -430,4 +641,62 @@ impl Objective for Hinge {
 
         self.update_predictions(examples, value)
     }
+
+    fn predict_leaf_value_weighted(&mut self, targets: &[NumT], examples: &[usize],
+                                   weights: &[NumT]) -> NumT {
+        let (mut value, mut wsum) = (0.0, 0.0);
+        for &i in examples {
+            let (t, p, w) = (targets[i], self.predictions[i], weights[i]);
+            if t < 0.5 { // neg class
+                let x = (2.0 + p).max(0.0);
+                value -= w*x*x;
+            } else {
+                let x = (2.0 - p).max(0.0);
+                value += w*x*x;
+            }
+            wsum += w;
+        }
+        self.update_predictions(examples, value / wsum)
+    }
+}
+
+
+
+
+
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn binary_prior_with_offsets() {
+        let config = Config::new();
+        let targets = [1.0, 0.0, 1.0, 1.0, 0.0, 1.0, 1.0, 0.0];
+        let offsets = [0.5, -1.0, 0.2, 0.0, 0.3, -0.4, 1.5, -0.2];
+
+        // without offsets, the Newton steps find the closed form prior
+        let mut plain = Binary::new();
+        plain.initialize(&config, &targets);
+        let mut zero = Binary::new();
+        zero.initialize_with_offsets(&config, &targets, &[0.0; 8]);
+        assert!((plain.bias() - zero.bias()).abs() < 1e-5);
+
+        // with offsets, the prior is optimal relative to the offsets: the gradients sum to zero
+        let mut objective = Binary::new();
+        objective.initialize_with_offsets(&config, &targets, &offsets);
+        for (&p, &o) in objective.predictions().iter().zip(&offsets) {
+            assert!((p - o - objective.bias()).abs() < 1e-5);
+        }
+        objective.update(&targets);
+        let gsum: NumT = objective.gradients().iter().sum();
+        assert!(gsum.abs() < 1e-5, "gradient sum {}", gsum);
+
+        // a constant offset shifts the prior by that constant
+        let mut shifted = Binary::new();
+        shifted.initialize_with_offsets(&config, &targets, &[1.0; 8]);
+        assert!((shifted.bias() - (plain.bias() - 1.0)).abs() < 1e-5);
+    }
 }
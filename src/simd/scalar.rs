@@ -0,0 +1,101 @@
+/*
+ * Copyright 2019 DTAI Research Group - KU Leuven.
+ * License: Apache License 2.0
+ * Author: Laurens Devos
+*/
+
+use std::slice;
+use std::mem::size_of;
+
+use crate::bitblock::BitBlock;
+
+// Portable implementations of the kernels in `simd`. They compute exactly the same integer sums
+// as the AVX2 versions, one 32-bit unit at a time. Most x86 CPUs without AVX2 still have the
+// `popcnt` instruction, so the kernels are compiled a second time with `popcnt` enabled.
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "popcnt")]
+unsafe fn with_popcnt<R, F: FnOnce() -> R>(f: F) -> R { f() }
+
+macro_rules! popcnt_dispatch {
+    ($body:expr) => {{
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("popcnt") { return with_popcnt(|| $body); }
+        }
+        $body
+    }}
+}
+
+#[inline(always)]
+fn cast<T>(blocks: &[BitBlock]) -> &[T] {
+    let n = blocks.len() * (BitBlock::nbytes() / size_of::<T>());
+    unsafe { slice::from_raw_parts(blocks.as_ptr() as *const T, n) }
+}
+
+/// Sum the 32-bit units of `slice` masked by `mask`. A block of a bitslice of width `w` contains
+/// `w` bit planes of `8 / w` units each; plane `p` has weight `2^p`. Mask unit `k` covers unit
+/// `k % (8 / w)` of each plane in block `k / (8 / w)`.
+#[inline(always)]
+fn btslce_summ<M>(width: usize, slice: &[BitBlock], mask: M) -> u64
+where M: Fn(usize) -> u32
+{
+    let slice = cast::<u32>(slice);
+    let nunits = 8 / width;
+    let nblocks = slice.len() / 8;
+
+    let mut sum = 0;
+    for k in 0..nblocks * nunits {
+        let m = mask(k);
+        let offset = (k / nunits) * 8 + k % nunits;
+        for plane in 0..width {
+            sum += ((slice[offset + plane * nunits] & m).count_ones() as u64) << plane;
+        }
+    }
+    sum
+}
+
+pub unsafe fn bitvec_count_and_uc(v1: &[BitBlock], v2: &[BitBlock]) -> u64 {
+    let nblocks = usize::min(v1.len(), v2.len());
+    let v1 = cast::<u64>(&v1[0..nblocks]);
+    let v2 = cast::<u64>(&v2[0..nblocks]);
+    popcnt_dispatch!(v1.iter().zip(v2).map(|(x, y)| (x & y).count_ones() as u64).sum())
+}
+
+pub unsafe fn bitvec_count_and_c(v1: &[BitBlock], idxs: &[BitBlock], v2: &[BitBlock]) -> u64 {
+    let nblocks = usize::min(v1.len(), idxs.len());
+    let v1 = cast::<u32>(&v1[0..nblocks]);
+    let idxs = cast::<u32>(&idxs[0..nblocks]);
+    let v2 = cast::<u32>(v2);
+    popcnt_dispatch!(v1.iter().zip(idxs)
+                     .map(|(x, &i)| (x & v2[i as usize]).count_ones() as u64).sum())
+}
+
+macro_rules! btslce_summ_fns {
+    ($width:expr, $uc:ident, $c:ident) => {
+        pub unsafe fn $uc(slice: &[BitBlock], nm: &[BitBlock], fm: &[BitBlock]) -> u64 {
+            let (nm, fm) = (cast::<u32>(nm), cast::<u32>(fm));
+            popcnt_dispatch!(btslce_summ($width, slice, |k| nm[k] & fm[k]))
+        }
+
+        pub unsafe fn $c(slice: &[BitBlock], indices: &[BitBlock], nm: &[BitBlock],
+                         fm: &[BitBlock]) -> u64
+        {
+            let (indices, nm, fm) = (cast::<u32>(indices), cast::<u32>(nm), cast::<u32>(fm));
+            popcnt_dispatch!(btslce_summ($width, slice, |k| nm[k] & fm[indices[k] as usize]))
+        }
+    }
+}
+
+btslce_summ_fns!(1, btslce_summ1_uc, btslce_summ1_c);
+btslce_summ_fns!(2, btslce_summ2_uc, btslce_summ2_c);
+btslce_summ_fns!(4, btslce_summ4_uc, btslce_summ4_c);
+btslce_summ_fns!(8, btslce_summ8_uc, btslce_summ8_c);
+
+pub unsafe fn or_assign(bv0: &mut [BitBlock], bv1: &[BitBlock]) {
+    for (b0, b1) in bv0.iter_mut().zip(bv1.iter()) {
+        let p0 = b0.as_mut_ptr() as *mut u64;
+        let p1 = b1.as_ptr() as *const u64;
+        for i in 0..4 { *p0.add(i) |= *p1.add(i); }
+    }
+}
@@ -6,10 +6,9 @@
 
 use std::alloc;
 use std::borrow::{Borrow, BorrowMut};
-use std::fmt::Debug;
 use std::marker::PhantomData;
 use std::mem::{size_of, align_of};
-use std::ops::{Sub, Deref, DerefMut};
+use std::ops::{Deref, DerefMut};
 use std::ptr;
 use std::slice;
 
@@ -21,7 +20,7 @@ use crate::NumT;
 use crate::bitblock::{BitBlock, get_bit, set_bit, get_bitpos, get_blockpos};
 use crate::simd;
 
-pub type SliceRange = (u32, u32); // TODO change to struct SliceRange(u32, u32), impl is_null_range()
+pub type SliceRange = (usize, usize); // TODO change to struct SliceRange, impl is_null_range()
 pub type BitVecRef<'a> = BitVec<&'a [BitBlock]>;
 pub type BitVecMut<'a> = BitVec<&'a mut [BitBlock]>;
 pub type BitSliceRef<'a, L> = BitSlice<&'a [BitBlock], L>;
@@ -42,7 +41,7 @@ where T: Clone {
     free: Vec<SliceRange>,
 
     /// Stores the ends of slices so we can always recover full slices when freed/reused.
-    slice_ends: HashMap<u32, u32>,
+    slice_ends: HashMap<usize, usize>,
 }
 
 impl <T> SliceStore<T>
@@ -146,11 +145,11 @@ where T: Clone {
 impl <T> SliceStore<T>
 where T: Clone {
     pub fn get_slice(&self, r: SliceRange) -> &[T] {
-        &self.buffer()[r.0 as usize..r.1 as usize]
+        &self.buffer()[r.0..r.1]
     }
 
     pub fn get_slice_mut(&mut self, r: SliceRange) -> &mut [T] {
-        &mut self.buffer_mut()[r.0 as usize..r.1 as usize]
+        &mut self.buffer_mut()[r.0..r.1]
     }
 
     pub fn get_two_slices_mut(&mut self, r1: SliceRange, r2: SliceRange)
@@ -158,15 +157,15 @@ where T: Clone {
     {
         debug_assert!(r1 != r2);
         if r1.0 < r2.0 {
-            let (s1, s2) = self.buffer_mut().split_at_mut(r2.0 as usize);
-            (&mut s1[r1.0 as usize..r1.1 as usize], &mut s2[0..(r2.1-r2.0) as usize])
+            let (s1, s2) = self.buffer_mut().split_at_mut(r2.0);
+            (&mut s1[r1.0..r1.1], &mut s2[0..r2.1-r2.0])
         } else {
-            let (s1, s2) = self.buffer_mut().split_at_mut(r1.0 as usize);
-            (&mut s2[0..(r1.1-r1.0) as usize], &mut s1[r2.0 as usize..r2.1 as usize])
+            let (s1, s2) = self.buffer_mut().split_at_mut(r1.0);
+            (&mut s2[0..r1.1-r1.0], &mut s1[r2.0..r2.1])
         }
     }
 
-    pub fn alloc_slice(&mut self, len: u32, value: T) -> SliceRange {
+    pub fn alloc_slice(&mut self, len: usize, value: T) -> SliceRange {
         assert!(len > 0);
         // Check if we can use a free range
         // Note that we forget the full length of a slice when a shorter slice replaces a longer
@@ -188,19 +187,18 @@ where T: Clone {
         let m = old_len_unaligned % elem_align;
         let old_len = if m == 0 { old_len_unaligned }
                       else { old_len_unaligned + elem_align - m };
-        let new_len = old_len + len as usize;
+        let new_len = old_len + len;
 
-        assert!(new_len < u32::max_value() as usize);
         debug_assert!(self.ptr as usize % self.byte_align == 0);
 
         self.resize(new_len, value);
-        let slice_start = old_len as u32;
-        let slice_end = new_len as u32;
+        let slice_start = old_len;
+        let slice_end = new_len;
         self.slice_ends.insert(slice_start, slice_end);
         (slice_start, slice_end)
     }
 
-    pub fn alloc_slice_default(&mut self, len: u32) -> SliceRange
+    pub fn alloc_slice_default(&mut self, len: usize) -> SliceRange
     where T: Default {
         self.alloc_slice(len, T::default())
     }
@@ -225,98 +223,6 @@ unsafe impl <T: Clone + Sync> Sync for SliceStore<T> {}
 
 
 
-// ------------------------------------------------------------------------------------------------
-
-pub struct HistStore<T>
-where T: Clone + Default {
-    slice_store: SliceStore<T>,
-    hist_layout: Vec<u32>,
-}
-
-impl <T> HistStore<T>
-where T: Clone + Default {
-    pub fn new<I>(bin_size_iter: I) -> Self
-    where I: Iterator<Item = u32> {
-        let mut hist_layout = Vec::new();
-        let mut accum = 0;
-
-        hist_layout.push(0);
-        for bin_size in bin_size_iter {
-            accum += bin_size;
-            hist_layout.push(accum);
-        }
-
-        HistStore {
-            slice_store: SliceStore::new(8192),
-            hist_layout: hist_layout,
-        }
-    }
-
-    fn get_histogram_range(&self, feat_id: usize) -> (usize, usize) {
-        let lo = self.hist_layout[feat_id];
-        let hi = self.hist_layout[feat_id + 1];
-        (lo as usize, hi as usize)
-    }
-
-    pub fn get_hist(&self, hists_range: SliceRange, feat_id: usize) -> &[T] {
-        let (lo, hi) = self.get_histogram_range(feat_id);
-        &self.slice_store.get_slice(hists_range)[lo..hi]
-    }
-
-    pub fn get_hist_mut(&mut self, hists_range: SliceRange, feat_id: usize) -> &mut [T] {
-        let (lo, hi) = self.get_histogram_range(feat_id);
-        &mut self.slice_store.get_slice_mut(hists_range)[lo..hi]
-    }
-
-    pub fn hists_subtract(&mut self, parent_range: SliceRange, left_range: SliceRange,
-                          right_range: SliceRange)
-    where T: Sub<Output=T> {
-        let (plo, phi) = parent_range;
-        let (llo, _) = left_range;
-        let (rlo, _) = right_range;
-
-        let buffer = self.slice_store.buffer_mut();
-
-        debug_assert_eq!(phi-plo, *self.hist_layout.last().unwrap());
-
-        for i in 0..(phi-plo) {
-            let parent = buffer[(plo+i) as usize].clone();
-            let left   = buffer[(llo+i) as usize].clone();
-            buffer[(rlo+i) as usize] = parent - left;
-        }
-    }
-
-    pub fn debug_print(&self, hists_range: SliceRange)
-    where T: Debug {
-        println!("Histograms");
-        for feat_id in 0..self.hist_layout.len()-1 {
-            for (i, val) in self.get_hist(hists_range, feat_id).iter().enumerate() {
-                println!("{:4}: {:?}", i, val);
-            }
-            println!();
-        }
-    }
-
-    pub fn alloc_hists(&mut self) -> SliceRange {
-        let total_bins = *self.hist_layout.last().unwrap();
-        self.slice_store.alloc_slice(total_bins, T::default())
-    }
-
-    pub fn free_hists(&mut self, r: SliceRange) {
-        self.slice_store.free_slice(r);
-    }
-
-    pub fn reset(&mut self) {
-        self.slice_store.reset();
-    }
-}
-
-
-
-
-
-
-
 // ------------------------------------------------------------------------------------------------
 
 pub struct BitBlockStore {
@@ -334,8 +240,7 @@ impl BitBlockStore {
     }
 
     pub fn alloc_zero_blocks(&mut self, nblocks: usize) -> SliceRange {
-        assert!(nblocks < u32::max_value() as usize);
-        self.slice_store.alloc_slice(nblocks as u32, BitBlock::zeros())
+        self.slice_store.alloc_slice(nblocks, BitBlock::zeros())
     }
 
     pub fn alloc_zero_bits(&mut self, nbits: usize) -> SliceRange {
@@ -344,8 +249,7 @@ impl BitBlockStore {
     }
 
     pub fn alloc_one_blocks(&mut self, nblocks: usize) -> SliceRange {
-        debug_assert!(nblocks < u32::max_value() as usize);
-        self.slice_store.alloc_slice(nblocks as u32, BitBlock::ones())
+        self.slice_store.alloc_slice(nblocks, BitBlock::ones())
     }
 
     pub fn alloc_one_bits(&mut self, nbits: usize) -> SliceRange {
@@ -426,7 +330,7 @@ impl BitBlockStore {
         -> BitSliceRef<L>
     where L: 'a + BitSliceLayout
     {
-        debug_assert!((range.1 - range.0) as usize % L::width() == 0);
+        debug_assert_eq!((range.1 - range.0) % L::width(), 0);
         BitSlice::new(self.get_bitvec(range))
     }
 
@@ -434,7 +338,7 @@ impl BitBlockStore {
         -> BitSliceMut<L>
     where L: 'a + BitSliceLayout
     {
-        debug_assert!((range.1 - range.0) as usize % L::width() == 0);
+        debug_assert_eq!((range.1 - range.0) % L::width(), 0);
         BitSlice::new(self.get_bitvec_mut(range))
     }
 
@@ -1045,61 +949,12 @@ bitslice_layout!(BitSliceLayout8, 8);
 mod test {
     use crate::NumT;
     use crate::bitblock::BitBlock;
-    use crate::slice_store::{SliceStore, HistStore, BitBlockStore};
+    use crate::slice_store::{SliceStore, BitBlockStore};
     use crate::slice_store::{BitSliceLayout};
     use crate::slice_store::{BitSliceLayout1, BitSliceLayout2, BitSliceLayout4, BitSliceLayout8};
 
     const BOUNDS: (NumT, NumT) = (0.0, 1.0);
 
-    #[test]
-    fn histograms() {
-        let cardinalities = vec![4, 6, 16];
-        let mut store = HistStore::<f32>::new(cardinalities.iter().cloned());
-        let r0;
-        let r1;
-        let r2;
-        let ptr0;
-        let ptr1;
-        let ptr2;
-
-        {
-            r0 = store.alloc_hists();
-
-            assert_eq!(store.get_hist(r0, 0).len(), 4);
-            assert_eq!(store.get_hist(r0, 1).len(), 6);
-            assert_eq!(store.get_hist(r0, 2).len(), 16);
-
-            ptr0 = &store.get_hist(r0, 0)[0] as *const f32;
-        }
-
-        {
-            r1 = store.alloc_hists();
-            store.free_hists(r0);
-            r2 = store.alloc_hists();
-
-            ptr1 = &store.get_hist(r1, 0)[0] as *const f32;
-        }
-
-        {
-            ptr2 = &store.get_hist(r2, 0)[0] as *const f32;
-
-            assert_ne!(ptr0, ptr1);
-            assert_eq!(ptr0, ptr2); // histograms reused
-        }
-    }
-
-    #[test]
-    fn hist_sum() {
-        let cardinalities = vec![4, 6, 16];
-        let mut store = HistStore::<f32>::new(cardinalities.iter().cloned());
-
-        let r0 = store.alloc_hists();
-        store.get_hist_mut(r0, 2).iter_mut().enumerate().for_each(|(i, x)| *x = i as f32);
-        store.get_hist(r0, 2).iter().enumerate().for_each(|(i, &x)| assert!(x == i as f32));
-        store.get_hist(r0, 0).iter().for_each(|&x| assert_eq!(x, 0.0));
-        store.get_hist(r0, 1).iter().for_each(|&x| assert_eq!(x, 0.0));
-    }
-
     #[test]
     fn slicestore_aligned() {
         let elem_align = 16;
@@ -4,6 +4,159 @@
  * Author: Laurens Devos
 */
 
+use std::ops::{Add, Sub};
+use std::sync::Arc;
+
+use crate::NumT;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HistVal {
+    pub grad_sum: NumT,
+    pub example_count: u64,
+}
+
+impl HistVal {
+    pub fn unpack(self) -> (NumT, u64) {
+        (self.grad_sum, self.example_count)
+    }
+}
+
+impl Sub for HistVal {
+    type Output = HistVal;
+    fn sub(self, other: HistVal) -> HistVal {
+        HistVal {
+            grad_sum: self.grad_sum - other.grad_sum,
+            example_count: self.example_count - other.example_count,
+        }
+    }
+}
+
+impl Add for HistVal {
+    type Output = HistVal;
+    fn add(self, other: HistVal) -> HistVal {
+        HistVal {
+            grad_sum: self.grad_sum + other.grad_sum,
+            example_count: self.example_count + other.example_count,
+        }
+    }
+}
+
+
+
+
+// ------------------------------------------------------------------------------------------------
+
+/// The gradient sums and example counts of the left side of each possible split of each feature,
+/// stored consecutively in a single buffer.
 pub struct Histogram {
+    layout: Arc<Vec<usize>>, // feature `f` has the bins `layout[f]..layout[f+1]`
+    values: Vec<HistVal>,
+}
+
+impl Histogram {
+    pub fn new(layout: Arc<Vec<usize>>) -> Histogram {
+        let nbins = *layout.last().expect("empty histogram layout");
+        Histogram {
+            layout,
+            values: vec![HistVal::default(); nbins],
+        }
+    }
+
+    /// Cumulative bin counts of the given features, as used by `Histogram::new`.
+    pub fn layout<I>(nbins_iter: I) -> Vec<usize>
+    where I: Iterator<Item = usize> {
+        let mut layout = vec![0];
+        let mut accum = 0;
+        for nbins in nbins_iter {
+            accum += nbins;
+            layout.push(accum);
+        }
+        layout
+    }
+
+    pub fn reset(&mut self) {
+        self.values.iter_mut().for_each(|v| *v = HistVal::default());
+    }
+
+    fn range(&self, feat_id: usize) -> (usize, usize) {
+        (self.layout[feat_id], self.layout[feat_id + 1])
+    }
+
+    pub fn get(&self, feat_id: usize) -> &[HistVal] {
+        let (lo, hi) = self.range(feat_id);
+        &self.values[lo..hi]
+    }
+
+    /// The histogram of each feature as a separate mutable slice, so that the histograms of
+    /// different features can be written concurrently.
+    pub fn features_mut(&mut self) -> Vec<&mut [HistVal]> {
+        let mut features = Vec::with_capacity(self.layout.len() - 1);
+        let mut rest = &mut self.values[..];
+        for w in self.layout.windows(2) {
+            let (hist, tail) = rest.split_at_mut(w[1] - w[0]);
+            features.push(hist);
+            rest = tail;
+        }
+        features
+    }
+
+    /// Set the histograms of all features to `parent - left`.
+    pub fn subtract_all(&mut self, parent: &Histogram, left: &Histogram) {
+        debug_assert!(Arc::ptr_eq(&self.layout, &parent.layout));
+        debug_assert!(Arc::ptr_eq(&self.layout, &left.layout));
+        for ((v, &p), &l) in self.values.iter_mut().zip(&parent.values).zip(&left.values) {
+            *v = p - l;
+        }
+    }
+
+    /// Set the histogram of a single feature to `parent - left`.
+    pub fn subtract(&mut self, parent: &Histogram, left: &Histogram, feat_id: usize) {
+        let (lo, hi) = self.range(feat_id);
+        for i in lo..hi {
+            self.values[i] = parent.values[i] - left.values[i];
+        }
+    }
+}
+
+
+
+
+
+
+
+
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn histogram_subtract() {
+        let layout = Arc::new(Histogram::layout([2, 3].iter().cloned()));
+        assert_eq!(&layout[..], &[0, 2, 5]);
+
+        let mut parent = Histogram::new(layout.clone());
+        let mut left = Histogram::new(layout.clone());
+        let mut right = Histogram::new(layout.clone());
+        for i in 0..5 {
+            parent.values[i] = HistVal { grad_sum: 10.0, example_count: 10 };
+            left.values[i] = HistVal { grad_sum: i as NumT, example_count: i as u64 };
+        }
+
+        right.subtract(&parent, &left, 1);
+        assert_eq!(right.get(0)[1].unpack(), (0.0, 0));
+        assert_eq!(right.get(1)[2].unpack(), (6.0, 6));
+
+        right.subtract_all(&parent, &left);
+        assert_eq!(right.get(0)[1].unpack(), (9.0, 9));
+
+        for (feat_id, hist) in right.features_mut().into_iter().enumerate() {
+            assert_eq!(hist.len(), feat_id + 2);
+        }
 
+        right.reset();
+        assert_eq!(right.get(1)[2].unpack(), (0.0, 0));
+    }
 }
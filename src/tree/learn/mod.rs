@@ -7,12 +7,12 @@
 mod tree_learner_context;
 mod tree_learner;
 mod node_to_split;
-mod split_candidate;
+mod split;
 mod histogram;
 
 pub use tree_learner_context::TreeLearnerContext;
 pub use tree_learner::TreeLearner;
-pub use split_candidate::SplitCandidate;
 
 use node_to_split::NodeToSplit;
-use histogram::Histogram;
+use split::Split;
+use histogram::{Histogram, HistVal};
@@ -6,42 +6,50 @@
 
 use std::sync::Arc;
 
-use crate::NumT;
-use crate::bitblock::{BitBlock, BitBlocks};
+use crate::{NumT, POS_INF, NEG_INF};
+use crate::bitblock::BitBlocks;
 use crate::bitset::Bitset;
 use crate::bitslice::Bitslice;
 use crate::tree::learn::Histogram;
 
+/// Contains information about a node that is still to be split.
 pub struct NodeToSplit {
-    node_id: usize,
-    examples_count: u32,
-    grad_sum: NumT,
-
-    histogram: Histogram,
-    instance_set: Bitset,
-    gradients: Arc<Bitslice>,
-    indexes: Arc<BitBlocks>, // empty if no compression
+    pub node_id: usize,
+    pub example_count: u64,
+    pub grad_sum: NumT,
+    pub bounds: (NumT, NumT), // leaf value bounds imposed by monotone constraints
+    pub features: Vec<usize>, // features considered at this node, histograms only for these
+
+    // information about the examples that sort to this node:
+    // - histogram for each feature
+    // - the examples in this node, one bit per example (only the non-zero u32s if compressed)
+    // - the discretized gradients, aligned with the instance set
+    // - the global u32 index of each u32 of the instance set (empty if not compressed)
+    // Uncompressed children share the gradients and the indexes of their parent.
+    pub histogram: Histogram,
+    pub instance_set: Bitset,
+    pub gradients: Arc<Bitslice>,
+    pub indexes: Arc<BitBlocks>,
 }
 
 impl NodeToSplit {
+    pub fn new(node_id: usize, histogram: Histogram, instance_set: Bitset,
+               gradients: Arc<Bitslice>, indexes: Arc<BitBlocks>) -> NodeToSplit
+    {
+        NodeToSplit {
+            node_id,
+            example_count: 0,
+            grad_sum: 0.0,
+            bounds: (NEG_INF, POS_INF),
+            features: Vec::new(),
+            histogram,
+            instance_set,
+            gradients,
+            indexes,
+        }
+    }
 
     pub fn is_compressed(&self) -> bool {
         !self.indexes.is_empty()
     }
-
-
-
-    /*
-    #[inline(always)]
-    pub unsafe fn get_instance_set_block_ptr(&self, index: usize) -> *const BitBlock {
-        safety_check!(self.indexes.is_empty());
-        safety_check!(self.instance_set.len() > index);
-        self.instance_set.as_ptr().add(index)
-    }
-
-    #[inline(always)]
-    pub unsafe fn get_instance_set_block_ptr_c(&self, index: usize) -> *const BitBlock {
-        0 as *const BitBlock
-    }
-    */
 }
@@ -0,0 +1,27 @@
+/*
+ * Copyright 2019 DTAI Research Group - KU Leuven.
+ * License: Apache License 2.0
+ * Author: Laurens Devos
+*/
+
+use crate::NumT;
+use crate::tree::SplitCrit;
+
+/// The best split of a node.
+pub struct Split {
+    pub split_crit: SplitCrit, // we use SplitCrit::NoSplit to indicate that no split is possible
+    pub split_id: usize,
+    pub gain: NumT,
+    pub mid: NumT, // separates the leaf values of the children of a monotone split
+}
+
+impl Split {
+    pub fn no_split() -> Split {
+        Split {
+            split_crit: SplitCrit::no_split(),
+            split_id: 0,
+            gain: 0.0,
+            mid: 0.0,
+        }
+    }
+}
@@ -4,15 +4,34 @@
  * Author: Laurens Devos
 */
 
-use crate::NumT;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+use log::{warn, debug};
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::SmallRng;
+use rayon::prelude::*;
+
+use crate::{NumT, CatT};
+use crate::config::Config;
+use crate::data::{Data, FeatType};
+use crate::dataset::{Dataset, shuffle_first_k};
+use crate::slice_store::BitVecRef;
+use crate::bitblock::{BitBlock, BitBlocks};
+use crate::bitset::Bitset;
 use crate::bitslice::*;
-use crate::tree::learn::TreeLearnerContext;
-use crate::tree::learn::NodeToSplit;
-use crate::tree::learn::SplitCandidate;
+use crate::count_and_sum::*;
+use crate::objective::Objective;
+use crate::tree::{Tree, SplitType, SplitCrit};
+use crate::tree::learn::{TreeLearnerContext, NodeToSplit, Split, HistVal};
 
+/// Helper macro for calling code that is specialized for each value of `discr_nbits`. Each
+/// `BitsliceLayout` is a type parameter, so rustc generates a version of the function for each
+/// width and propagates the constants.
 macro_rules! dispatch_on_discr_nbits {
     ($self:expr, $generic_fun:ident; $( $args:expr ),*) => {{
-        match $self.ctx.config.discr_nbits {
+        match $self.ctx.discr_nbits {
             1 => $self.$generic_fun::<BitsliceLayout1>($( $args, )*),
             2 => $self.$generic_fun::<BitsliceLayout2>($( $args, )*),
             4 => $self.$generic_fun::<BitsliceLayout4>($( $args, )*),
@@ -23,44 +42,948 @@ macro_rules! dispatch_on_discr_nbits {
 }
 
 
-pub struct TreeLearner<'a, 'b>
+
+
+/// Minimum number of u32s of an instance set processed by a single rayon task.
+const PAR_MIN_U32S: usize = 1024;
+
+
+
+
+// ------------------------------------------------------------------------------------------------
+
+pub struct TreeLearner<'a, 'b, 'c>
 where 'a: 'b, // a lives longer than b
+      'c: 'b, // c lives longer than b
 {
-    ctx: &'b TreeLearnerContext<'a>,
-    
+    ctx: &'b mut TreeLearnerContext<'a>,
+    data: &'b Data,
+    dataset: &'b Dataset<'c>,
+    objective: &'b mut dyn Objective,
+    tree: Tree,
+    best_first: bool,
+    rng: SmallRng,
+    level_features: Vec<Vec<usize>>, // feature sample of each depth of the tree
 }
 
-impl <'a, 'b> TreeLearner<'a, 'b> {
+impl <'a, 'b, 'c> TreeLearner<'a, 'b, 'c>
+where 'a: 'b {
+    pub fn new(ctx: &'b mut TreeLearnerContext<'a>, dataset: &'b Dataset<'c>,
+               supercats: Vec<Vec<CatT>>, objective: &'b mut dyn Objective)
+        -> Self
+    {
+        ctx.reset();
+        let tree = Tree::new(ctx.config.max_tree_depth, supercats);
+        let best_first = match ctx.config.growth_policy.as_str() {
+            "depthwise" => false,
+            "leafwise"  => true,
+            _ => panic!("unknown growth_policy '{}'", ctx.config.growth_policy),
+        };
+        let rng = SmallRng::seed_from_u64(ctx.seed);
+        TreeLearner {
+            ctx,
+            data: dataset.data(),
+            dataset,
+            objective,
+            tree,
+            best_first,
+            rng,
+            level_features: Vec::new(),
+        }
+    }
+
+    pub fn train(mut self) -> Tree {
+        let root_n2s = self.get_root_n2s();
+        let root_split = self.find_best_split(&root_n2s);
+        self.ctx.n2s_stack.push((root_n2s, root_split));
+
+        while let Some((n2s, split)) = self.pop_node_to_split() {
+            let node_id = n2s.node_id;
+
+            if split.split_crit.is_no_split() || self.max_leaves_reached() {
+                // predict leaf value, easy case: we have the instance set
+                self.predict_leaf_value(&n2s);
+            } else {
+                let (left_id, right_id) = self.tree.split_node(node_id,
+                                                               split.split_crit.clone());
+
+                // If the children can still be split, push to split later.
+                // Else, the children are leafs: generate leaf values.
+                if !self.tree.is_max_leaf_node(left_id) && !self.max_leaves_reached() {
+                    let (left_n2s, right_n2s) = self.get_left_right_n2s(&n2s, &split, left_id,
+                                                                         right_id);
+                    let right_split = self.find_best_split(&right_n2s);
+                    let left_split = self.find_best_split(&left_n2s);
+                    self.ctx.n2s_stack.push((right_n2s, right_split));
+                    self.ctx.n2s_stack.push((left_n2s, left_split));
+                } else {
+                    // predict leaf values, hard case: we don't have instance sets for children.
+                    self.predict_child_leaf_values(&n2s, &split, left_id, right_id);
+                }
+            }
+
+            // the histogram can be reused; gradients/indexes are dropped when no longer shared
+            self.ctx.free_histogram(n2s.histogram);
+        }
+
+        if self.tree.nnodes() == 1 { warn!("tree with single root node"); }
+
+        // if bagging enabled, then update predictions for the out-of-bag examples
+        self.predict_out_of_bag_examples();
+
+        self.tree // can have only a single root node
+    }
+
+    /// Depth-first growth takes the last pushed node, best-first growth takes the node whose best
+    /// split has the highest gain.
+    fn pop_node_to_split(&mut self) -> Option<(NodeToSplit, Split)> {
+        let stack = &mut self.ctx.n2s_stack;
+        if self.best_first {
+            let best = (0..stack.len()).max_by(|&i, &j| {
+                stack[i].1.gain.partial_cmp(&stack[j].1.gain).unwrap_or(Ordering::Equal)
+            })?;
+            Some(stack.swap_remove(best))
+        } else {
+            stack.pop()
+        }
+    }
+
+    fn max_leaves_reached(&self) -> bool {
+        let max_leaves = self.ctx.config.max_leaves;
+        max_leaves > 0 && self.tree.nleafs() >= max_leaves
+    }
+
+    /// The best split of each feature is found in parallel. The results are combined in feature
+    /// order, so ties are broken the same way regardless of the number of threads.
+    fn find_best_split(&self, n2s: &NodeToSplit) -> Split {
+        let config = self.ctx.config;
+        let (dataset, data) = (self.dataset, self.data);
+        let path_features = self.get_path_features(n2s.node_id);
+
+        let feature_splits: Vec<Split> = n2s.features
+            .par_iter()
+            .filter(|&&feat_id| Self::interaction_allowed(config, &path_features, feat_id))
+            .map(|&feat_id| Self::find_best_feature_split(config, dataset, data, n2s, feat_id))
+            .collect();
+
+        let mut best_split = Split::no_split();
+        let mut best_gain = config.min_gain;
+        for split in feature_splits {
+            if split.gain > best_gain {
+                best_gain = split.gain;
+                best_split = split;
+            }
+        }
+        best_split
+    }
+
+    /// The split with the highest gain greater than `min_gain` of a single feature, or
+    /// `Split::no_split()` if there is none.
+    fn find_best_feature_split(config: &Config, dataset: &Dataset, data: &Data,
+                               n2s: &NodeToSplit, feat_id: usize) -> Split
+    {
+        let mut best_split = Split::no_split();
+        let mut best_gain = config.min_gain;
+        let min_examples = u64::from(config.min_examples_leaf);
+
+        let (pgrad, pcount) = (n2s.grad_sum, n2s.example_count);
+        let ploss = Self::get_loss(config, pgrad, pcount);
+
+        let hist = n2s.histogram.get(feat_id);
+        let nbins = dataset.get_nbins(feat_id);
+        let constraint = Self::monotone_constraint(config, feat_id);
+
+        for (split_id, histval) in hist.iter().enumerate().take(nbins) {
+            let (lgrad, lcount) = histval.unpack();
+            let (rgrad, rcount) = (pgrad - lgrad, pcount - lcount);
+
+            if lcount < min_examples || rcount < min_examples { continue; }
+
+            let lloss = Self::get_loss(config, lgrad, lcount);
+            let rloss = Self::get_loss(config, rgrad, rcount);
+            let gain = ploss - lloss - rloss;
+
+            if gain > best_gain {
+                // Reject splits that violate the monotone constraint of the feature: the left
+                // child contains the examples with the smaller feature values.
+                let lvalue = Self::get_leaf_estimate(config, lgrad, lcount, n2s.bounds);
+                let rvalue = Self::get_leaf_estimate(config, rgrad, rcount, n2s.bounds);
+                if (constraint > 0 && lvalue > rvalue) || (constraint < 0 && lvalue < rvalue) {
+                    continue;
+                }
+
+                best_gain = gain;
+                best_split.split_id = split_id;
+                best_split.gain = gain;
+                best_split.mid = 0.5 * (lvalue + rvalue);
+
+                let split_value = dataset.get_split_value(feat_id, split_id);
+                let split_type = match data.feat_type(feat_id) {
+                    FeatType::LoCardCat => SplitType::LoCardCatEq,
+                    FeatType::HiCardCat => SplitType::HiCardCatLt,
+                    FeatType::Numerical => SplitType::NumLt,
+                };
+
+                best_split.split_crit = SplitCrit {
+                    split_type,
+                    feature_id: feat_id,
+                    split_value,
+                }
+            }
+        }
+        best_split
+    }
+
+    /// The features used by the splits on the path from the root to this node, only collected
+    /// when there are interaction constraints.
+    fn get_path_features(&self, mut node_id: usize) -> Vec<usize> {
+        let mut features = Vec::new();
+        if self.ctx.config.interaction_constraints.is_empty() { return features; }
+        while node_id != 0 {
+            node_id = self.tree.parent(node_id);
+            let feat_id = self.tree.split_crit(node_id).feature_id;
+            if !features.contains(&feat_id) { features.push(feat_id); }
+        }
+        features
+    }
+
+    /// Can `feat_id` be used to split a node with the given path features? There must be an
+    /// interaction constraint group that contains all of them.
+    fn interaction_allowed(config: &Config, path_features: &[usize], feat_id: usize) -> bool {
+        let groups = &config.interaction_constraints;
+        if groups.is_empty() || path_features.iter().all(|&f| f == feat_id) { return true; }
+        groups.iter().any(|group| {
+            group.contains(&feat_id) && path_features.iter().all(|f| group.contains(f))
+        })
+    }
+
+    fn get_root_n2s(&mut self) -> NodeToSplit {
+        dispatch_on_discr_nbits!(self, get_root_n2s_aux;)
+    }
+
+    fn get_root_n2s_aux<L>(&mut self) -> NodeToSplit
+    where L: BitsliceLayout {
+        let bounds     = self.dataset.grad_bounds(self.objective.bounds());
+        let nexamples  = self.dataset.nactive_examples();
+        let gradients  = self.objective.gradients();
+        let weights    = self.dataset.example_weights();
+
+        let exact         = self.ctx.config.exact_gradients;
+
+        // compressed instance sets store the index of each of their u32s as a u32, and the 1-bit
+        // and 2-bit kernels gather with these as signed 32-bit offsets: at most 2^36 examples
+        assert!(nexamples / 32 <= i32::MAX as usize, "at most 2^36 examples are supported");
+
+        // put gradients in bitslice, and keep the exact gradients if requested
+        let mut grad_sum = 0;
+        let mut grad_slice = Bitslice::new();
+        let mut grad_view = grad_slice.with_layout_mut::<L>();
+        grad_view.resize(nexamples);
+        let exact_gradients = &mut self.ctx.exact_gradients;
+        exact_gradients.clear();
+        if exact { exact_gradients.resize(nexamples, 0.0); }
+        let stochastic = self.ctx.config.stochastic_rounding;
+        let mut rounding_rng = SmallRng::seed_from_u64(self.ctx.rounding_seed);
+        let track_error = self.ctx.config.discr_nbits_error_threshold > 0.0;
+        let (mut abs_error, mut abs_sum) = (0.0f64, 0.0f64);
+        self.dataset.active_examples_iter(|i, j| {
+            let v = match weights { Some(w) => w[j] * gradients[j], None => gradients[j] };
+            let x = if stochastic {
+                let u = rounding_rng.gen::<NumT>();
+                grad_view.set_scaled_value_stochastic(i, v, bounds, u)
+            } else {
+                grad_view.set_scaled_value(i, v, bounds)
+            };
+            grad_sum += x as u64;
+            if exact { exact_gradients[i] = v; }
+            if track_error {
+                let clipped = v.max(bounds.0).min(bounds.1);
+                abs_error += (L::linproj(x as NumT, 1.0, bounds) - clipped).abs() as f64;
+                abs_sum += clipped.abs() as f64;
+            }
+        });
+        self.ctx.quantization_error = if abs_sum > 0.0 { (abs_error / abs_sum) as NumT }
+                                      else              { 0.0 };
+
+        let histogram = self.ctx.alloc_histogram();
+        let mut n2s = NodeToSplit::new(0, histogram, Bitset::ones(nexamples),
+                                       Arc::new(grad_slice), Arc::new(BitBlocks::empty()));
+        n2s.example_count = nexamples as u64;
+        n2s.grad_sum = if exact {
+            self.ctx.exact_gradients.iter().map(|&g| g as f64).sum::<f64>() as NumT
+        } else {
+            L::linproj(grad_sum as NumT, nexamples as NumT, bounds)
+        };
+
+        // build histograms
+        let features = self.sample_node_features(0);
+        self.build_histograms(&mut n2s, &features);
+        n2s.features = features;
+
+        n2s
+    }
+
+    fn get_left_right_n2s(&mut self, parent_n2s: &NodeToSplit, split: &Split, left_id: usize,
+                          right_id: usize) -> (NodeToSplit, NodeToSplit)
+    {
+        let feat_id = split.split_crit.feature_id;
+        let (pgrad, pcount) = (parent_n2s.grad_sum, parent_n2s.example_count);
+        let (lgrad, lcount) = parent_n2s.histogram.get(feat_id)[split.split_id].unpack();
+        let (rgrad, rcount) = (pgrad - lgrad, pcount - lcount);
+
+        let (lbounds, rbounds) = self.get_child_bounds(parent_n2s, split);
+        let depth = self.tree.depth(left_id);
+
+        let mut left_n2s = self.split_examples(parent_n2s, left_id, split, |m| m);
+        left_n2s.grad_sum = lgrad;
+        left_n2s.example_count = lcount;
+        left_n2s.bounds = lbounds;
+        left_n2s.features = self.sample_node_features(depth);
+
+        let mut right_n2s = self.split_examples(parent_n2s, right_id, split, |m| !m);
+        right_n2s.grad_sum = rgrad;
+        right_n2s.example_count = rcount;
+        right_n2s.bounds = rbounds;
+        right_n2s.features = self.sample_node_features(depth);
+
+        // build the histograms of the child with the fewest examples, derive the other's
+        let (small_n2s, large_n2s) = if rcount < lcount { (&mut right_n2s, &mut left_n2s) }
+                                     else               { (&mut left_n2s, &mut right_n2s) };
+        let features = std::mem::take(&mut small_n2s.features);
+        self.build_histograms(small_n2s, &features);
+        small_n2s.features = features;
+        self.derive_histograms(parent_n2s, small_n2s, large_n2s);
+
+        (left_n2s, right_n2s)
+    }
+
+    /// The instance set of a child is the parent's instance set masked by the split set, or by
+    /// its complement for the right child. The zero u32s are dropped from the child's instance
+    /// set when there are enough of them.
+    fn split_examples<F>(&mut self, parent_n2s: &NodeToSplit, child_id: usize, split: &Split,
+                         f: F) -> NodeToSplit
+    where F: Fn(u32) -> u32 + Sync
+    {
+        let feat_id = split.split_crit.feature_id;
+        let split_set = self.dataset.get_bitvec(feat_id, split.split_id);
+        let split_set = split_set.cast::<u32>();
+        let parent_mask = parent_n2s.instance_set.cast::<u32>();
+        let parent_indexes = parent_n2s.indexes.cast::<u32>();
+        let compressed = parent_n2s.is_compressed();
+        let n_u32 = parent_mask.len();
+
+        let mut instance_set = Bitset::zeros(parent_n2s.instance_set.len() * BitBlock::nbits());
+        let child_mask = instance_set.cast_mut::<u32>();
+
+        let zero_count: usize = child_mask
+            .par_iter_mut()
+            .enumerate()
+            .with_min_len(PAR_MIN_U32S)
+            .map(|(j, child)| {
+                // j is node index (compr), i is global index
+                let i = if compressed { parent_indexes[j] as usize } else { j };
+                let mask = parent_mask[j] & f(split_set[i]);
+                *child = mask;
+                (mask == 0) as usize
+            })
+            .sum();
+
+        let histogram = self.ctx.alloc_histogram();
+
+        // We encountered enough zero blocks to apply compression
+        let zero_ratio = zero_count as NumT / n_u32 as NumT;
+        let ratio_ok = zero_ratio > self.ctx.config.compression_threshold;
+        let nblocks_ok = n_u32 > 8;
+        if ratio_ok && nblocks_ok {
+            debug!("N{:03}->N{:03} applying compression, #zero = {}/{} (ratio={:.2})",
+                   parent_n2s.node_id, child_id, zero_count, n_u32, zero_ratio);
+
+            let n_u32_child = n_u32 - zero_count; // number of non-zero blocks in child
+            assert!(n_u32_child > 0, "compression: all blocks zero?");
+            let (instance_set, gradients, indexes) = dispatch_on_discr_nbits!(self,
+                compress_examples; parent_n2s, &instance_set, n_u32_child);
+            NodeToSplit::new(child_id, histogram, instance_set, gradients, indexes)
+        } else {
+            NodeToSplit::new(child_id, histogram, instance_set, parent_n2s.gradients.clone(),
+                             parent_n2s.indexes.clone())
+        }
+    }
+
+    /// Copy the non-zero u32s of `child_mask` to a new instance set, and store their global
+    /// indexes and gradients.
+    fn compress_examples<L>(&self, parent_n2s: &NodeToSplit, child_mask: &Bitset,
+                            n_u32_child: usize) -> (Bitset, Arc<Bitslice>, Arc<BitBlocks>)
+    where L: BitsliceLayout + Sync
+    {
+        let nvalues = n_u32_child * 32;
+        let mut instance_set = Bitset::zeros(nvalues);
+        let mut indexes = BitBlocks::zero_bits(nvalues);
+        let mut gradients = Bitslice::new();
+        gradients.with_layout_mut::<L>().resize(nvalues);
+
+        let parent_grads = parent_n2s.gradients.with_layout::<L>();
+        let parent_indexes = parent_n2s.indexes.cast::<u32>();
+        let compressed = parent_n2s.is_compressed();
+        let child_mask = child_mask.cast::<u32>();
+
+        // node index (compr) in the parent of each u32 of the child, in order
+        let sources: Vec<usize> = child_mask
+            .par_iter()
+            .enumerate()
+            .with_min_len(PAR_MIN_U32S)
+            .filter(|&(_, &mask)| mask != 0)
+            .map(|(j, _)| j)
+            .collect();
+
+        debug_assert_eq!(sources.len(), n_u32_child);
+
+        instance_set.cast_mut::<u32>()
+            .par_iter_mut()
+            .zip(indexes.cast_mut::<u32>().par_iter_mut())
+            .zip(sources.par_iter())
+            .with_min_len(PAR_MIN_U32S)
+            .for_each(|((child_mask_compr, child_index), &j)| {
+                *child_mask_compr = child_mask[j];
+                *child_index = if compressed { parent_indexes[j] } else { j as u32 };
+            });
+
+        // each 256-bit superblock of the gradient bitslice holds all lanes of a fixed number of
+        // consecutive u32s, so the superblocks can be filled independently
+        let sbw = L::superblock_width();
+        gradients.as_bitblocks_mut().cast_mut::<u32>()
+            .par_chunks_mut(BitBlock::nbytes() / 4)
+            .zip(sources.par_chunks(sbw))
+            .with_min_len(PAR_MIN_U32S / 8)
+            .for_each(|(superblock, sources)| {
+                for (superblock_j, &j) in sources.iter().enumerate() {
+                    for lane in 0..L::width() {
+                        superblock[L::superblock_offset(superblock_j, lane)] =
+                            parent_grads.get_block(j, lane);
+                    }
+                }
+            });
+
+        (instance_set, Arc::new(gradients), Arc::new(indexes))
+    }
+
+    fn build_histograms(&self, n2s: &mut NodeToSplit, features: &[usize]) {
+        dispatch_on_discr_nbits!(self, build_histograms_aux; n2s, features)
+    }
+
+    fn build_histograms_aux<L>(&self, n2s: &mut NodeToSplit, features: &[usize])
+    where L: BitsliceLayout
+    {
+        let grad_bounds = self.dataset.grad_bounds(self.objective.bounds());
+        let min_examples = u64::from(self.ctx.config.min_examples_leaf);
+        let dataset = self.dataset;
+        let exact_gradients = if self.ctx.config.exact_gradients {
+            Some(&self.ctx.exact_gradients[..])
+        } else { None };
+
+        let NodeToSplit { ref mut histogram, ref instance_set, ref gradients, ref indexes, .. }
+            = *n2s;
+
+        // Each feature's histogram is owned by a single task, and each of its bins is written
+        // once, so no synchronization is needed.
+        let mut feature_hists: Vec<Option<&mut [HistVal]>> = histogram.features_mut()
+            .into_iter()
+            .map(Some)
+            .collect();
+        let tasks: Vec<(usize, &mut [HistVal])> = features.iter()
+            .map(|&feat_id| (feat_id, feature_hists[feat_id].take().expect("duplicate feature")))
+            .collect();
+
+        tasks.into_par_iter().for_each(|(feat_id, hist)| {
+            let nbins = dataset.get_nbins(feat_id);
+            hist[0..nbins].par_iter_mut().enumerate().for_each(|(split_id, histval)| {
+                let split_set = dataset.get_bitvec(feat_id, split_id);
+                let example_count = Self::count_instances_left(instance_set, indexes,
+                                                               &split_set);
+                let grad_sum = if example_count < min_examples { 0.0 } else {
+                    match exact_gradients {
+                        Some(exact_gradients) => Self::sum_exact_gradients_left(
+                            instance_set, indexes, &split_set, exact_gradients),
+                        None => {
+                            let sum = Self::sum_gradients_left::<L>(instance_set, gradients,
+                                                                    indexes, &split_set);
+                            L::linproj(sum as NumT, example_count as NumT, grad_bounds)
+                        }
+                    }
+                };
+                *histval = HistVal { grad_sum, example_count };
+            });
+        });
+    }
+
+    /// Number of examples in the instance set that are also in the split set.
+    fn count_instances_left(instance_set: &Bitset, indexes: &BitBlocks, split_set: &[BitBlock])
+        -> u64
+    {
+        if indexes.is_empty() {
+            count_and2_c00(instance_set, split_set)
+        } else {
+            count_and2_c10(indexes, instance_set, split_set)
+        }
+    }
+
+    /// Sum of the exact gradients of the examples in the instance set that are also in the split
+    /// set, used instead of `sum_gradients_left` when `exact_gradients` is enabled.
+    fn sum_exact_gradients_left(instance_set: &Bitset, indexes: &BitBlocks,
+                                split_set: &BitVecRef, exact_gradients: &[NumT]) -> NumT
+    {
+        let indexes = indexes.cast::<u32>();
+        let split_set = split_set.cast::<u32>();
+        let mut sum = 0.0f64;
+        for (k, &mask) in instance_set.cast::<u32>().iter().enumerate() {
+            // k is node index (compr), i is global index
+            let i = if indexes.is_empty() { k } else { indexes[k] as usize };
+            let mut bits = mask & split_set[i];
+            while bits != 0 {
+                let b = bits.trailing_zeros() as usize;
+                sum += exact_gradients[i * 32 + b] as f64;
+                bits &= bits - 1;
+            }
+        }
+        sum as NumT
+    }
+
+    /// Sum of the discretized gradients of the examples in the instance set that are also in the
+    /// split set.
+    fn sum_gradients_left<L>(instance_set: &Bitset, gradients: &Bitslice, indexes: &BitBlocks,
+                             split_set: &[BitBlock]) -> u64
+    where L: BitsliceLayout
+    {
+        let gradients = gradients.as_bitblocks();
+        match (indexes.is_empty(), L::width()) {
+            (true,  1) => sum_and3_w1_c00(gradients, instance_set, split_set),
+            (true,  2) => sum_and3_w2_c00(gradients, instance_set, split_set),
+            (true,  4) => sum_and3_w4_c00(gradients, instance_set, split_set),
+            (true,  8) => sum_and3_w8_c00(gradients, instance_set, split_set),
+            (false, 1) => sum_and3_w1_c10(gradients, indexes, instance_set, split_set),
+            (false, 2) => sum_and3_w2_c10(gradients, indexes, instance_set, split_set),
+            (false, 4) => sum_and3_w4_c10(gradients, indexes, instance_set, split_set),
+            (false, 8) => sum_and3_w8_c10(gradients, indexes, instance_set, split_set),
+            _ => panic!("invalid discr_nbits"),
+        }
+    }
+
+    /// The histograms of a child are the parent's minus its sibling's. With per-node feature
+    /// sampling, the histograms of features missing in the parent or the sibling are built
+    /// instead.
+    fn derive_histograms(&self, parent_n2s: &NodeToSplit, sibling_n2s: &NodeToSplit,
+                         n2s: &mut NodeToSplit)
+    {
+        if n2s.features == parent_n2s.features && n2s.features == sibling_n2s.features {
+            n2s.histogram.subtract_all(&parent_n2s.histogram, &sibling_n2s.histogram);
+            return;
+        }
+
+        let (derive, build): (Vec<usize>, Vec<usize>) = n2s.features.iter()
+            .partition(|f| parent_n2s.features.binary_search(f).is_ok()
+                        && sibling_n2s.features.binary_search(f).is_ok());
+        for &feat_id in &derive {
+            n2s.histogram.subtract(&parent_n2s.histogram, &sibling_n2s.histogram, feat_id);
+        }
+        if !build.is_empty() {
+            self.build_histograms(n2s, &build);
+        }
+    }
+
+    /// The features considered at a node of the given depth: a sample of the tree's features for
+    /// each level, and a sample of the level's features for each node.
+    fn sample_node_features(&mut self, depth: usize) -> Vec<usize> {
+        let config = self.ctx.config;
+        let features = if config.feature_fraction_bylevel < 1.0 {
+            while self.level_features.len() <= depth {
+                let sample = Self::sample_features(&mut self.rng, self.dataset.active_features(),
+                                                   config.feature_fraction_bylevel);
+                self.level_features.push(sample);
+            }
+            self.level_features[depth].clone()
+        } else {
+            self.dataset.active_features().to_vec()
+        };
+        if config.feature_fraction_bynode < 1.0 {
+            Self::sample_features(&mut self.rng, &features, config.feature_fraction_bynode)
+        } else {
+            features
+        }
+    }
+
+    /// Sorted random sample of at least one of the given features.
+    fn sample_features(rng: &mut SmallRng, features: &[usize], fraction: NumT) -> Vec<usize> {
+        let n = features.len();
+        let k = ((n as NumT * fraction).round() as usize).max(1).min(n);
+        let mut buffer = features.to_vec();
+        shuffle_first_k(rng, k, &mut buffer);
+        buffer.truncate(k);
+        buffer
+    }
 
+    /// With GOSS, `grad_sum` is the sum of the weighted gradients, but `example_count` is the
+    /// number of sampled examples and not the sum of their weights: the histograms count bits, and
+    /// weighted counts would need a second count per bin of the small-gradient examples. The
+    /// gains of children with many small-gradient examples are therefore somewhat too large. The
+    /// leaf values are computed by the objective with the weights.
+    fn get_loss(config: &Config, grad_sum: NumT, example_count: u64) -> NumT {
+        let lambda = config.reg_lambda;
+        -0.5 * ((grad_sum * grad_sum) / (example_count as NumT + lambda))
+    }
 
-    fn count_instances_left(&self, n2s: &NodeToSplit, split_cand: &SplitCandidate) -> u64 {
+    /// Estimate of a leaf value based on the discretized gradients, within the given bounds. Only
+    /// used to enforce monotone constraints. With GOSS, the count is not weighted (see
+    /// `get_loss`), but the constraints still hold: the leaf values are clamped to the bounds.
+    fn get_leaf_estimate(config: &Config, grad_sum: NumT, example_count: u64,
+                         bounds: (NumT, NumT)) -> NumT
+    {
+        let lambda = config.reg_lambda;
+        let value = -grad_sum / (example_count as NumT + lambda);
+        value.max(bounds.0).min(bounds.1)
+    }
+
+    /// +1 for an increasing, -1 for a decreasing constraint, 0 if the feature is unconstrained.
+    fn monotone_constraint(config: &Config, feat_id: usize) -> isize {
+        config.monotone_constraints.get(feat_id).cloned().unwrap_or(0)
+    }
 
-        match (n2s.is_compressed(), split_cand.is_compressed()) {
-            (false, false) => {
-                0
-            },
-            (true, false) => {
-                0
-            },
-            (false, true) => {
-                0
-            },
-            (true, true) => {
-                0
+    /// The leaf values in the subtree of the left child of a monotone split lie on one side of
+    /// `split.mid`, those of the right child on the other side.
+    fn get_child_bounds(&self, n2s: &NodeToSplit, split: &Split)
+        -> ((NumT, NumT), (NumT, NumT))
+    {
+        let (lo, hi) = n2s.bounds;
+        match Self::monotone_constraint(self.ctx.config, split.split_crit.feature_id) {
+            c if c > 0 => ((lo, split.mid), (split.mid, hi)),
+            c if c < 0 => ((split.mid, hi), (lo, split.mid)),
+            _ => ((lo, hi), (lo, hi)),
+        }
+    }
+
+    /// The objective's leaf value, weighted by the GOSS example weights if enabled.
+    fn objective_leaf_value(objective: &mut dyn Objective, dataset: &Dataset, targets: &[NumT],
+                            examples: &[usize]) -> NumT {
+        match dataset.example_weights() {
+            Some(weights) => objective.predict_leaf_value_weighted(targets, examples, weights),
+            None => objective.predict_leaf_value(targets, examples),
+        }
+    }
+
+    /// With sampling with replacement, `examples` can contain the same example several times. The
+    /// copies count for the leaf value, but the objective also added the leaf `value` to the
+    /// prediction of each copy, so the extra additions are undone and the copies removed.
+    fn remove_duplicates(objective: &mut dyn Objective, examples: &mut Vec<usize>,
+                         config: &Config, value: NumT)
+    {
+        if !config.example_replacement { return; }
+        for w in examples.windows(2) { // sorted, so copies are adjacent
+            if w[0] == w[1] { objective.update_out_of_bag_prediction(w[1], -value); }
+        }
+        examples.dedup();
+    }
+
+    /// Clamp a leaf value to its bounds. The objective already added the unclamped value to the
+    /// predictions of the `examples` in the leaf, so these are corrected.
+    fn clamp_leaf_value(objective: &mut dyn Objective, examples: &[usize], config: &Config,
+                        value: NumT, bounds: (NumT, NumT)) -> NumT
+    {
+        let lr = config.learning_rate;
+        let clamped = value.max(lr * bounds.0).min(lr * bounds.1);
+        if clamped != value {
+            for &i in examples {
+                objective.update_out_of_bag_prediction(i, clamped - value);
             }
         }
+        clamped
     }
 
-    fn sum_gradients_left_aux<L>(&self, n2s: &NodeToSplit, split_cand: &SplitCandidate)
-        -> NumT
-    where L: BitsliceLayout 
+    /// Collect the dataset indexes of the examples in the instance set of `n2s` in ascending
+    /// order. `f` maps a u32 of the instance set and its global u32 index to the bits to keep.
+    fn collect_examples<F>(dataset: &Dataset, n2s: &NodeToSplit, examples: &mut Vec<usize>, f: F)
+    where F: Fn(u32, usize) -> u32
     {
-        0.0
+        let masks = n2s.instance_set.cast::<u32>();
+        let indexes = n2s.indexes.cast::<u32>();
+        let compressed = n2s.is_compressed();
+        let sampling = dataset.example_sampling_enabled();
+
+        examples.clear();
+        for (j, &mask) in masks.iter().enumerate() {
+            let i = if compressed { indexes[j] as usize } else { j };
+            let mut mask = f(mask, i);
+            while mask != 0 {
+                let k = i * 32 + mask.trailing_zeros() as usize;
+                examples.push(if sampling { dataset.map_index(k) } else { k });
+                mask &= mask - 1;
+            }
+        }
+        safety_check!(!examples.is_empty());
     }
 
-    fn sum_gradients_left(&self, n2s: &NodeToSplit, split_cand: &SplitCandidate) -> NumT {
-        dispatch_on_discr_nbits!(self, sum_gradients_left_aux; n2s, split_cand)
+    fn predict_leaf_value(&mut self, n2s: &NodeToSplit) {
+        let targets = self.data.get_target();
+        let examples = &mut self.ctx.example_buffer;
+
+        if n2s.node_id == 0 { warn!("prediction root node value"); }
+
+        Self::collect_examples(self.dataset, n2s, examples, |m, _| m);
+        let value = Self::objective_leaf_value(self.objective, self.dataset, targets, examples);
+        Self::remove_duplicates(self.objective, examples, self.ctx.config, value);
+        let value = Self::clamp_leaf_value(self.objective, examples, self.ctx.config, value,
+                                           n2s.bounds);
+        self.tree.set_value(n2s.node_id, value);
+
+        debug!("N{:03} leaf value {} (no more splits)", n2s.node_id, value);
+    }
+
+    fn predict_child_leaf_values(&mut self, n2s: &NodeToSplit, split: &Split, left_id: usize,
+                                 right_id: usize) {
+        let feat_id = split.split_crit.feature_id;
+        let (lbounds, rbounds) = self.get_child_bounds(n2s, split);
+
+        let targets = self.data.get_target();
+        let split_set = self.dataset.get_bitvec(feat_id, split.split_id);
+        let split_set = split_set.cast::<u32>();
+        let examples = &mut self.ctx.example_buffer;
+
+        Self::collect_examples(self.dataset, n2s, examples, |m, i| m & split_set[i]);
+        let left_value = Self::objective_leaf_value(self.objective, self.dataset, targets, examples);
+        Self::remove_duplicates(self.objective, examples, self.ctx.config, left_value);
+        let left_value = Self::clamp_leaf_value(self.objective, examples, self.ctx.config,
+                                                left_value, lbounds);
+
+        Self::collect_examples(self.dataset, n2s, examples, |m, i| m & !split_set[i]);
+        let right_value = Self::objective_leaf_value(self.objective, self.dataset, targets, examples);
+        Self::remove_duplicates(self.objective, examples, self.ctx.config, right_value);
+        let right_value = Self::clamp_leaf_value(self.objective, examples, self.ctx.config,
+                                                 right_value, rbounds);
+
+        self.tree.set_value(left_id, left_value);
+        self.tree.set_value(right_id, right_value);
+
+        debug!("N{:03} leaf value {} (max leaf)", left_id, left_value);
+        debug!("N{:03} leaf value {} (max leaf)", right_id, right_value);
+    }
+
+    fn predict_out_of_bag_examples(&mut self) {
+        if !self.dataset.example_sampling_enabled() { return; }
+        let data = self.dataset.data();
+
+        for i in self.dataset.inactive_examples_iter() {
+            let prediction = self.tree.predict_single(data, i);
+            self.objective.update_out_of_bag_prediction(i, prediction);
+        }
     }
 }
 
 
+
+
+
+
+// ------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::objective::objective_from_name;
+
+    /// The best split of the root for the l2 objective at the initial predictions.
+    fn root_split(config: &Config, data: &Data) -> Split {
+        let target = data.get_target();
+        let mut objective = objective_from_name("l2").unwrap();
+        objective.initialize(config, target);
+        objective.update(target);
+        let mut dataset = Dataset::new(config, data);
+        dataset.update(config, objective.gradients(), objective.bounds());
+        let mut ctx = TreeLearnerContext::new(config, data);
+        let supercats = dataset.get_supercats();
+        let mut learner = TreeLearner::new(&mut ctx, &dataset, supercats, objective.as_mut());
+        let n2s = learner.get_root_n2s();
+        learner.find_best_split(&n2s)
+    }
+
+    /// Learn a single tree for the l2 objective at the initial predictions.
+    fn train_tree(config: &Config, data: &Data) -> Tree {
+        let target = data.get_target();
+        let mut objective = objective_from_name("l2").unwrap();
+        objective.initialize(config, target);
+        objective.update(target);
+        let mut dataset = Dataset::new(config, data);
+        dataset.update(config, objective.gradients(), objective.bounds());
+        let mut ctx = TreeLearnerContext::new(config, data);
+        let supercats = dataset.get_supercats();
+        TreeLearner::new(&mut ctx, &dataset, supercats, objective.as_mut()).train()
+    }
+
+    /// Check the split of each internal node, and the value of each leaf.
+    fn check_tree(tree: &Tree, nodes: &[(SplitType, usize, NumT)]) {
+        assert_eq!(tree.nnodes(), nodes.len());
+        for (node_id, (split_type, feat_id, value)) in nodes.iter().enumerate() {
+            let split_crit = tree.split_crit(node_id);
+            assert_eq!(&split_crit.split_type, split_type, "node {}", node_id);
+            if split_crit.is_no_split() {
+                assert!((tree.node_value(node_id) - value).abs() < 1e-5, "node {}: {} vs {}",
+                        node_id, tree.node_value(node_id), value);
+            } else {
+                assert_eq!(split_crit.feature_id, *feat_id, "node {}", node_id);
+                assert_eq!(split_crit.split_value, *value, "node {}", node_id);
+            }
+        }
+    }
+
+    /// Naive count and discretized gradient sum of the examples in both the instance set and the
+    /// split set.
+    fn count_and_sum_naive<L>(instance_set: &Bitset, gradients: &Bitslice, indexes: &BitBlocks,
+                              split_set: &Bitset) -> (u64, u64)
+    where L: BitsliceLayout
+    {
+        let gradients = gradients.with_layout::<L>();
+        let indexes = indexes.cast::<u32>();
+        let (mut count, mut sum) = (0, 0);
+        for j in 0..instance_set.cast::<u32>().len() * 32 {
+            let i = if indexes.is_empty() { j } else { indexes[j / 32] as usize * 32 + j % 32 };
+            if instance_set.get_bit(j) && split_set.get_bit(i) {
+                count += 1;
+                sum += u64::from(gradients.get_value(j));
+            }
+        }
+        (count, sum)
+    }
+
+    fn check_count_and_sum_left<L>()
+    where L: BitsliceLayout
+    {
+        type Learner<'a, 'b, 'c> = TreeLearner<'a, 'b, 'c>;
+        let mut rng = SmallRng::seed_from_u64(L::width() as u64);
+        let nu32s = 200;
+        let random_bitset = |rng: &mut SmallRng, nu32s: usize| {
+            let mut bitset = Bitset::zeros(nu32s * 32);
+            bitset.cast_mut::<u32>()[0..nu32s].iter_mut().for_each(|x| *x = rng.gen());
+            bitset
+        };
+        let random_gradients = |rng: &mut SmallRng, nvalues: usize| {
+            let mut gradients = Bitslice::new();
+            let mut view = gradients.with_layout_mut::<L>();
+            view.resize(nvalues);
+            for k in 0..nvalues { view.set_value(k, rng.gen_range(0, L::nunique_values()) as u8); }
+            gradients
+        };
+        let split_set = random_bitset(&mut rng, nu32s);
+
+        // uncompressed: the instance set covers all examples; compressed: the instance set only
+        // stores two out of three u32s
+        let global: Vec<u32> = (0..nu32s as u32).filter(|i| i % 3 != 1).collect();
+        for &compressed in &[false, true] {
+            let n = if compressed { global.len() } else { nu32s };
+            let instance_set = random_bitset(&mut rng, n);
+            let gradients = random_gradients(&mut rng, n * 32);
+            let mut indexes = BitBlocks::empty();
+            if compressed {
+                indexes = BitBlocks::zero_bits(n * 32);
+                indexes.cast_mut::<u32>()[0..n].copy_from_slice(&global);
+            }
+            let (count, sum) = count_and_sum_naive::<L>(&instance_set, &gradients, &indexes,
+                                                        &split_set);
+            assert_eq!(Learner::count_instances_left(&instance_set, &indexes, &split_set), count);
+            assert_eq!(Learner::sum_gradients_left::<L>(&instance_set, &gradients, &indexes,
+                                                        &split_set), sum);
+        }
+    }
+
+    #[test]
+    fn count_and_sum_left() {
+        check_count_and_sum_left::<BitsliceLayout1>();
+        check_count_and_sum_left::<BitsliceLayout2>();
+        check_count_and_sum_left::<BitsliceLayout4>();
+        check_count_and_sum_left::<BitsliceLayout8>();
+    }
+
+    #[test]
+    fn numerical_splits() {
+        let mut config = Config::new();
+        config.csv_has_header = false;
+        config.max_tree_depth = 2;
+        let csv: String = (0..1024)
+            .map(|i| {
+                let (x, y) = (i / 256, i % 4);
+                format!("{},{},{}\n", x, y, 4 * (x >= 2) as u8 + (y >= 2) as u8)
+            })
+            .collect();
+        let data = Data::from_csv(&config, &csv).unwrap();
+
+        // half of the u32s of the children of the root are zero: they are compressed when the
+        // threshold is 0.25, not when it is 0.75
+        for &threshold in &[0.25, 0.75] {
+            config.compression_threshold = threshold;
+            for &nbits in &[1, 2, 4, 8] {
+                config.discr_nbits = nbits;
+                let tree = train_tree(&config, &data);
+                if nbits == 1 { // the discretized gradients in each child are equal
+                    check_tree(&tree, &[(SplitType::NumLt, 0, 1.0019531),
+                                        (SplitType::NoSplit, 0, -2.0),
+                                        (SplitType::NoSplit, 0, 2.0)]);
+                } else {
+                    check_tree(&tree, &[(SplitType::NumLt, 0, 1.0019531),
+                                        (SplitType::NumLt, 1, 1.0019531),
+                                        (SplitType::NumLt, 1, 1.0019531),
+                                        (SplitType::NoSplit, 0, -2.5),
+                                        (SplitType::NoSplit, 0, -1.5),
+                                        (SplitType::NoSplit, 0, 1.5),
+                                        (SplitType::NoSplit, 0, 2.5)]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn categorical_splits() {
+        let mut config = Config::new();
+        config.csv_has_header = false;
+        config.max_tree_depth = 2;
+        config.max_nbins = 4;
+        config.categorical_features = vec![0, 1];
+        let csv: String = (0..1024)
+            .map(|i| {
+                let (x, y) = (i % 4, i % 13);
+                format!("{},{},{}\n", x, y, 4 * (x == 2) as u8 + (y % 3 == 0) as u8)
+            })
+            .collect();
+        let data = Data::from_csv(&config, &csv).unwrap();
+        assert_eq!(data.feat_type(0), FeatType::LoCardCat);
+        assert_eq!(data.feat_type(1), FeatType::HiCardCat); // 13 categories, 4 supercategories
+
+        let tree = train_tree(&config, &data);
+        check_tree(&tree, &[(SplitType::LoCardCatEq, 0, 2.0),
+                            (SplitType::HiCardCatLt, 1, 0.0),
+                            (SplitType::HiCardCatLt, 1, 1.0),
+                            (SplitType::NoSplit, 0, 3.6152344),
+                            (SplitType::NoSplit, 0, 2.8091118),
+                            (SplitType::NoSplit, 0, -0.5486074),
+                            (SplitType::NoSplit, 0, -1.3847656)]);
+    }
+
+    #[test]
+    fn goss_gain() {
+        let mut config = Config::new();
+        config.csv_has_header = false;
+        config.example_sampling = String::from("goss");
+        config.top_rate = 0.2;
+        config.other_rate = 0.3;
+        config.discr_nbits = 8;
+        let csv: String = (0..1000)
+            .map(|i| { let (x, y) = (i % 37, i % 11); format!("{},{},{}\n", x, y, x * x + y) })
+            .collect();
+        let data = Data::from_csv(&config, &csv).unwrap();
+
+        // the discretized weighted gradients, within the widened GOSS bounds, give the same split
+        // as the exact weighted gradients
+        let split = root_split(&config, &data);
+        config.exact_gradients = true;
+        let exact = root_split(&config, &data);
+        assert!(!exact.split_crit.is_no_split());
+        assert_eq!(split.split_crit.feature_id, exact.split_crit.feature_id);
+        assert_eq!(split.split_id, exact.split_id);
+        assert!((split.gain - exact.gain).abs() < 0.01 * exact.gain,
+                "{} vs {}", split.gain, exact.gain);
+    }
+}
@@ -4,9 +4,86 @@
  * Author: Laurens Devos
 */
 
+use std::sync::Arc;
+
+use crate::NumT;
 use crate::config::Config;
+use crate::data::Data;
+use crate::tree::learn::{NodeToSplit, Histogram, Split};
 
+/// Resources used by the tree learner that can be reused.
 pub struct TreeLearnerContext<'a> {
-    pub config: &'a Config
+    pub config: &'a Config,
+
+    pub(super) hist_layout: Arc<Vec<usize>>,
+    pub(super) hist_pool: Vec<Histogram>, // histograms of nodes that were already split
+
+    pub(super) n2s_stack: Vec<(NodeToSplit, Split)>, // nodes to split, and their best split
+    pub(super) example_buffer: Vec<usize>, // used for leaf value predictions
+    pub(super) exact_gradients: Vec<NumT>, // exact_gradients: gradient of each active example
+    pub(super) seed: u64, // per-level and per-node feature sampling
+    pub(super) rounding_seed: u64, // stochastic rounding of the gradients
+    pub(super) discr_nbits: usize, // number of discretization bits of the next tree
+    pub(super) quantization_error: NumT, // of the last tree, if discr_nbits_error_threshold > 0
+}
+
+impl <'a> TreeLearnerContext<'a> {
+    pub fn new(config: &'a Config, data: &'a Data) -> Self {
+        let nbins_iter = (0..data.nfeatures()).map(|feat_id| data.max_nbins(feat_id));
+
+        TreeLearnerContext {
+            config,
+
+            hist_layout: Arc::new(Histogram::layout(nbins_iter)),
+            hist_pool: Vec::new(),
+
+            n2s_stack: Vec::new(),
+            example_buffer: Vec::new(),
+            exact_gradients: Vec::new(),
+            seed: config.random_seed,
+            rounding_seed: config.random_seed,
+            discr_nbits: config.discr_nbits,
+            quantization_error: 0.0,
+        }
+    }
+
+    /// Set the seed of the per-level and per-node feature sampling of the next tree.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    /// Set the seed of the stochastic rounding of the gradients of the next tree.
+    pub fn set_rounding_seed(&mut self, seed: u64) {
+        self.rounding_seed = seed;
+    }
+
+    /// Set the number of bits used to discretize the gradients of the next tree.
+    pub fn set_discr_nbits(&mut self, discr_nbits: usize) {
+        assert!([1, 2, 4, 8].contains(&discr_nbits), "invalid discr_nbits");
+        self.discr_nbits = discr_nbits;
+    }
+
+    /// The relative quantization error of the gradients of the last tree, only computed when
+    /// `discr_nbits_error_threshold` is set.
+    pub fn quantization_error(&self) -> NumT {
+        self.quantization_error
+    }
+
+    pub(super) fn reset(&mut self) {
+        while let Some((n2s, _)) = self.n2s_stack.pop() {
+            self.free_histogram(n2s.histogram);
+        }
+    }
+
+    /// A zeroed histogram, reused from an earlier node if possible.
+    pub(super) fn alloc_histogram(&mut self) -> Histogram {
+        match self.hist_pool.pop() {
+            Some(mut histogram) => { histogram.reset(); histogram },
+            None => Histogram::new(self.hist_layout.clone()),
+        }
+    }
 
+    pub(super) fn free_histogram(&mut self, histogram: Histogram) {
+        self.hist_pool.push(histogram);
+    }
 }
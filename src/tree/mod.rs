@@ -5,10 +5,7 @@
 */
 
 pub mod learn;
-
-// TODO remove
-mod learner;
-pub use self::learner::{TreeLearner, TreeLearnerContext};
+pub use self::learn::{TreeLearner, TreeLearnerContext};
 
 mod tree;
 pub use self::tree::{Tree, SplitType, SplitCrit, AdditiveTree};
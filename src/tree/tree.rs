@@ -5,9 +5,11 @@
 */
 
 use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::io::{Read, Write, Result as IoResult};
 
 use crate::{NumT, CatT, into_cat};
 use crate::data::Data;
+use crate::checkpoint::{CheckpointWriter, CheckpointReader, invalid_data};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SplitType {
@@ -39,12 +41,17 @@ impl SplitCrit {
     }
 }
 
+/// A binary tree with a sparse node layout: nodes are allocated when their parent is split, the
+/// root has id 0, and the children of a node are stored next to each other (right = left + 1).
 #[derive(Clone)]
 pub struct Tree {
     ninternal: usize,
     max_depth: usize,
     split_crits: Vec<SplitCrit>,
     node_values: Vec<NumT>,
+    left_children: Vec<usize>, // 0 for leaves (the root is never a child)
+    parents: Vec<usize>,
+    depths: Vec<usize>,
 
     shrinkage: NumT,
     bias: NumT,
@@ -55,38 +62,41 @@ pub struct Tree {
 impl Tree {
     pub fn new(max_depth: usize, supercats: Vec<Vec<CatT>>) -> Tree {
         assert!(max_depth > 0);
-        let mut tree = Tree {
+        Tree {
             ninternal: 0, // the root is the only leaf
             max_depth: max_depth,
-            split_crits: Vec::new(),
-            node_values: Vec::new(),
+            split_crits: vec![SplitCrit::no_split()],
+            node_values: vec![0.0],
+            left_children: vec![0],
+            parents: vec![0],
+            depths: vec![0],
 
             shrinkage: 1.0,
             bias: 0.0,
 
             supercats,
-        };
-        let nnodes = tree.max_nnodes();
-
-        tree.split_crits.resize(nnodes, SplitCrit::no_split());
-        tree.node_values.resize(nnodes, 0.0);
-
-        tree
+        }
     }
 
     pub fn is_valid_node_id(&self, node_id: usize) -> bool { node_id < self.nnodes() }
-    pub fn left_child(&self, node_id: usize) -> usize { 2 * node_id + 1 }
-    pub fn right_child(&self, node_id: usize) -> usize { 2 * node_id + 2 }
-    pub fn parent(&self, node_id: usize) -> usize { (node_id - 1) / 2 }
+    pub fn is_leaf(&self, node_id: usize) -> bool { self.left_children[node_id] == 0 }
+    pub fn left_child(&self, node_id: usize) -> usize {
+        debug_assert!(!self.is_leaf(node_id));
+        self.left_children[node_id]
+    }
+    pub fn right_child(&self, node_id: usize) -> usize { self.left_child(node_id) + 1 }
+    pub fn parent(&self, node_id: usize) -> usize {
+        debug_assert!(node_id != 0);
+        self.parents[node_id]
+    }
+    pub fn depth(&self, node_id: usize) -> usize { self.depths[node_id] }
 
-    pub fn max_ninternal(&self) -> usize { (1 << self.max_depth) - 1 }
-    pub fn max_nleafs(&self) -> usize { 1 << self.max_depth }
     pub fn ninternal(&self) -> usize { self.ninternal }
     pub fn nleafs(&self) -> usize { self.ninternal + 1 }
-    pub fn max_nnodes(&self) -> usize { (1 << (self.max_depth+1)) - 1 }
     pub fn nnodes(&self) -> usize { self.ninternal() + self.nleafs() }
 
     pub fn node_value(&self, node_id: usize) -> NumT { self.node_values[node_id] }
+    pub fn split_crit(&self, node_id: usize) -> &SplitCrit { &self.split_crits[node_id] }
 
     pub fn max_depth(&self) -> usize { self.max_depth }
     pub fn set_value(&mut self, node_id: usize, value: NumT) {
@@ -96,16 +106,27 @@ impl Tree {
 
     /// Can this node still be split? We can't grow deeper than max_depth.
     pub fn is_max_leaf_node(&self, node_id: usize) -> bool {
-        node_id >= self.max_ninternal()
+        self.depths[node_id] >= self.max_depth
     }
 
-    pub fn split_node(&mut self, node_id: usize, split_crit: SplitCrit) {
-        assert!(self.split_crits[node_id].is_no_split());
+    /// Split a leaf, returns the ids of the new left and right child.
+    pub fn split_node(&mut self, node_id: usize, split_crit: SplitCrit) -> (usize, usize) {
+        assert!(self.is_leaf(node_id));
         assert!(!split_crit.is_no_split());
         assert!(!self.is_max_leaf_node(node_id));
 
+        let left_id = self.nnodes();
+        let depth = self.depths[node_id] + 1;
         self.split_crits[node_id] = split_crit;
+        self.left_children[node_id] = left_id;
+        self.split_crits.resize(left_id + 2, SplitCrit::no_split());
+        self.node_values.resize(left_id + 2, 0.0);
+        self.left_children.resize(left_id + 2, 0);
+        self.parents.resize(left_id + 2, node_id);
+        self.depths.resize(left_id + 2, depth);
         self.ninternal += 1;
+
+        (left_id, left_id + 1)
     }
 
     pub fn get_supercat(&self, feat_id: usize, split_id: usize) -> CatT {
@@ -113,12 +134,18 @@ impl Tree {
     }
 
     fn predict_leaf_id_of_example(&self, data: &Data, i: usize) -> usize {
+        self.predict_leaf_id(|feat_id| data.get_feature(feat_id)[i])
+    }
+
+    /// Find the leaf of an example given by a function returning its value for each feature.
+    fn predict_leaf_id<F>(&self, feature_value: F) -> usize
+    where F: Fn(usize) -> NumT {
         let mut node_id = 0;
         loop {
             let split_crit = &self.split_crits[node_id];
             let feat_id = split_crit.feature_id;
             let split_value = split_crit.split_value;
-            let value = data.get_feature(feat_id)[i];
+            let value = feature_value(feat_id);
 
             match split_crit.split_type {
                 SplitType::LoCardCatEq => {
@@ -173,6 +200,13 @@ impl Tree {
        self.shrinkage * (leaf_value + self.bias)
     }
 
+    fn predict_single_with<F>(&self, feature_value: F) -> NumT
+    where F: Fn(usize) -> NumT {
+       let leaf_id = self.predict_leaf_id(feature_value);
+       let leaf_value = self.node_values[leaf_id];
+       self.shrinkage * (leaf_value + self.bias)
+    }
+
     /// Predict and store the result as defined by `f` in `predict_buf`.
     pub fn predict_and<F>(&self, data: &Data, predict_buf: &mut [NumT], f: F)
     where F: Fn(NumT, &mut NumT) {
@@ -255,6 +289,23 @@ impl AdditiveTree {
         self.trees.push(tree);
     }
 
+    /// Keep only the first `ntrees` trees.
+    pub fn truncate(&mut self, ntrees: usize) {
+        self.trees.truncate(ntrees);
+    }
+
+    pub fn ntrees(&self) -> usize {
+        self.trees.len()
+    }
+
+    pub fn trees(&self) -> &[Tree] {
+        &self.trees
+    }
+
+    pub fn tree_mut(&mut self, index: usize) -> &mut Tree {
+        &mut self.trees[index]
+    }
+
     pub fn predict(&self, data: &Data) -> Vec<NumT> {
         let nexamples = data.nexamples();
         let mut accum = vec![0.0; nexamples];
@@ -262,14 +313,151 @@ impl AdditiveTree {
         accum
     }
 
+    /// The initial scores of `data`, if any, are added to the predictions.
     pub fn predict_buf(&self, data: &Data, buf: &mut [NumT]) {
         for x in buf.iter_mut() { *x = self.bias; }
+        if let Some(offsets) = data.init_scores() {
+            for (x, &o) in buf.iter_mut().zip(offsets) { *x += o; }
+        }
         for tree in &self.trees {
             tree.predict_and(data, buf, |prediction, accum| {
                 *accum += prediction;
             });
         }
     }
+
+    /// Verify that the predictions are monotone in feature `feat_id`: for each example in `data`,
+    /// the value of the feature is replaced by each of `ngrid` equally spaced values between the
+    /// feature's limits, and the resulting predictions must be non-decreasing (`constraint` > 0)
+    /// or non-increasing (`constraint` < 0).
+    pub fn check_monotone(&self, data: &Data, feat_id: usize, constraint: isize, ngrid: usize)
+        -> Result<(), String>
+    {
+        let (min, max) = data.feat_limits(feat_id);
+        let step = if ngrid > 1 { (max - min) / (ngrid - 1) as NumT } else { 0.0 };
+        let grid: Vec<NumT> = (0..ngrid).map(|k| min + k as NumT * step).collect();
+
+        for i in 0..data.nexamples() {
+            let mut prev = None;
+            for &x in &grid {
+                let feature_value = |f: usize| if f == feat_id { x } else { data.get_feature(f)[i] };
+                let p = self.trees.iter()
+                    .fold(self.bias, |acc, tree| acc + tree.predict_single_with(feature_value));
+
+                if let Some((px, pp)) = prev {
+                    if (constraint > 0 && p < pp) || (constraint < 0 && p > pp) {
+                        return Err(format!("not monotone in feature {} at example {}: \
+                                            {} -> {}, {} -> {}", feat_id, i, px, pp, x, p));
+                    }
+                }
+                prev = Some((x, p));
+            }
+        }
+        Ok(())
+    }
+}
+
+
+
+
+
+// ------------------------------------------------------------------------------------------------
+
+impl Tree {
+    pub(crate) fn write_checkpoint<W: Write>(&self, w: &mut CheckpointWriter<W>) -> IoResult<()> {
+        w.write_usize(self.max_depth)?;
+        w.write_usize(self.ninternal)?;
+        w.write_usizes(&self.left_children)?;
+        for split_crit in &self.split_crits {
+            let split_type = match split_crit.split_type {
+                SplitType::NoSplit     => 0,
+                SplitType::LoCardCatEq => 1,
+                SplitType::HiCardCatLt => 2,
+                SplitType::NumLt       => 3,
+            };
+            w.write_u64(split_type)?;
+            w.write_usize(split_crit.feature_id)?;
+            w.write_numt(split_crit.split_value)?;
+        }
+        w.write_numts(&self.node_values)?;
+        w.write_numt(self.shrinkage)?;
+        w.write_numt(self.bias)?;
+        w.write_usize(self.supercats.len())?;
+        for supercats in &self.supercats {
+            w.write_usize(supercats.len())?;
+            for &c in supercats { w.write_u64(c as u64)?; }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn read_checkpoint<R: Read>(r: &mut CheckpointReader<R>) -> IoResult<Tree> {
+        let max_depth = r.read_usize()?;
+        if max_depth == 0 { return Err(invalid_data("invalid tree depth")); }
+        let mut tree = Tree::new(max_depth, Vec::new());
+        tree.ninternal = r.read_usize()?;
+        tree.left_children = r.read_usizes()?;
+        let nnodes = tree.nnodes();
+        if tree.left_children.len() != nnodes || tree.left_children.iter().enumerate()
+            .any(|(i, &l)| l != 0 && (l <= i || l + 1 >= nnodes))
+        {
+            return Err(invalid_data("invalid tree structure"));
+        }
+        tree.split_crits.resize(nnodes, SplitCrit::no_split());
+        tree.parents.resize(nnodes, 0);
+        tree.depths.resize(nnodes, 0);
+        for node_id in 0..nnodes {
+            if !tree.is_leaf(node_id) {
+                let (left_id, depth) = (tree.left_children[node_id], tree.depths[node_id] + 1);
+                tree.depths[left_id] = depth;
+                tree.depths[left_id + 1] = depth;
+                tree.parents[left_id] = node_id;
+                tree.parents[left_id + 1] = node_id;
+            }
+        }
+        for split_crit in tree.split_crits.iter_mut() {
+            split_crit.split_type = match r.read_u64()? {
+                0 => SplitType::NoSplit,
+                1 => SplitType::LoCardCatEq,
+                2 => SplitType::HiCardCatLt,
+                3 => SplitType::NumLt,
+                _ => return Err(invalid_data("invalid split type")),
+            };
+            split_crit.feature_id = r.read_usize()?;
+            split_crit.split_value = r.read_numt()?;
+        }
+        tree.node_values = r.read_numts()?;
+        if tree.node_values.len() != nnodes {
+            return Err(invalid_data("invalid number of tree nodes"));
+        }
+        tree.shrinkage = r.read_numt()?;
+        tree.bias = r.read_numt()?;
+        let nsupercats = r.read_usize()?;
+        for _ in 0..nsupercats {
+            let n = r.read_usize()?;
+            let supercats = (0..n).map(|_| r.read_u64().map(|c| c as CatT))
+                .collect::<IoResult<Vec<CatT>>>()?;
+            tree.supercats.push(supercats);
+        }
+        Ok(tree)
+    }
+}
+
+impl AdditiveTree {
+    pub(crate) fn write_checkpoint<W: Write>(&self, w: &mut CheckpointWriter<W>) -> IoResult<()> {
+        w.write_numt(self.bias)?;
+        w.write_usize(self.trees.len())?;
+        for tree in &self.trees { tree.write_checkpoint(w)?; }
+        Ok(())
+    }
+
+    pub(crate) fn read_checkpoint<R: Read>(r: &mut CheckpointReader<R>)
+        -> IoResult<AdditiveTree>
+    {
+        let bias = r.read_numt()?;
+        let ntrees = r.read_usize()?;
+        let trees = (0..ntrees).map(|_| Tree::read_checkpoint(r)).collect::<IoResult<_>>()?;
+        Ok(AdditiveTree { bias, trees })
+    }
 }
 
 
@@ -283,14 +471,29 @@ impl AdditiveTree {
 
 #[cfg(test)]
 mod test {
-    use crate::tree::Tree;
+    use crate::tree::{Tree, SplitCrit, SplitType};
 
     #[test]
     fn test_tree() {
-        let tree = Tree::new(3, Vec::new());
+        let mut tree = Tree::new(3, Vec::new());
         assert_eq!(tree.max_depth(), 3);
-        assert_eq!(tree.max_nleafs(), 8);
-        assert_eq!(tree.max_ninternal(), 7);
-        assert_eq!(tree.max_nnodes(), 8+7);
+        assert_eq!(tree.nnodes(), 1);
+        assert!(tree.is_leaf(0));
+
+        let split = |f| SplitCrit { split_type: SplitType::NumLt, feature_id: f, split_value: 0.0 };
+        assert_eq!(tree.split_node(0, split(0)), (1, 2));
+        assert_eq!(tree.split_node(2, split(1)), (3, 4));
+        assert_eq!(tree.split_node(4, split(0)), (5, 6));
+        assert_eq!(tree.ninternal(), 3);
+        assert_eq!(tree.nleafs(), 4);
+        assert_eq!(tree.nnodes(), 7);
+        assert_eq!(tree.right_child(2), 4);
+        assert_eq!(tree.depth(6), 3);
+        assert_eq!(tree.parent(6), 4);
+        assert_eq!(tree.parent(3), 2);
+        assert_eq!(tree.parent(1), 0);
+        assert_eq!(tree.split_crit(4).feature_id, 0);
+        assert!(tree.is_max_leaf_node(5));
+        assert!(!tree.is_max_leaf_node(1));
     }
 }